@@ -0,0 +1,75 @@
+//! The stereo alignment tuned while running, saved with S to `--save-calibration` and applied
+//! from `--load-calibration`, as the command line flags that set it up.
+
+use anyhow::Context;
+use clap::{parser::ValueSource, ArgMatches};
+use std::{fs, path::Path, str::FromStr};
+
+use crate::{settings::Settings, Cli};
+
+/// The flags for the current alignment; roll and scale can't be changed at runtime, so they're
+/// the ones given on startup.
+pub fn flags(settings: &Settings, args: &Cli) -> String {
+    format!(
+        "--convergence {} --depth-bias {} --camera2-offset-x {} --camera2-offset-y {} \
+         --camera1-roll {} --camera2-roll {} --camera1-scale {} --camera2-scale {}",
+        settings.convergence,
+        settings.depth_bias,
+        settings.camera2_offset[0],
+        settings.camera2_offset[1],
+        args.camera1_roll,
+        args.camera2_roll,
+        args.camera1_scale,
+        args.camera2_scale,
+    )
+}
+
+pub fn save(path: &Path, settings: &Settings, args: &Cli) -> anyhow::Result<()> {
+    fs::write(path, flags(settings, args) + "\n")
+        .with_context(|| format!("Couldn't write {}", path.display()))
+}
+
+/// Applies the flags saved in `path` to `args`, except those given on the command line or as
+/// `ANAGLYPH_*` environment variables, which take precedence over the file.
+pub fn load(path: &Path, args: &mut Cli, matches: &ArgMatches) -> anyhow::Result<()> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    let mut tokens = contents.split_whitespace();
+    while let Some(flag) = tokens.next() {
+        let name = flag
+            .strip_prefix("--")
+            .with_context(|| format!("{}: expected a flag, got {flag}", path.display()))?;
+        let value = tokens
+            .next()
+            .with_context(|| format!("{}: {flag} has no value", path.display()))?;
+        let source = matches.value_source(&name.replace('-', "_"));
+        if matches!(
+            source,
+            Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+        ) {
+            continue;
+        }
+        let result = match name {
+            "convergence" => parse(value, &mut args.convergence),
+            "depth-bias" => parse(value, &mut args.depth_bias),
+            "camera2-offset-x" => parse(value, &mut args.camera2_offset_x),
+            "camera2-offset-y" => parse(value, &mut args.camera2_offset_y),
+            "camera1-roll" => parse(value, &mut args.camera1_roll),
+            "camera2-roll" => parse(value, &mut args.camera2_roll),
+            "camera1-scale" => parse(value, &mut args.camera1_scale),
+            "camera2-scale" => parse(value, &mut args.camera2_scale),
+            _ => anyhow::bail!("{}: {flag} isn't part of a calibration", path.display()),
+        };
+        result.with_context(|| format!("{}: invalid value for {flag}", path.display()))?;
+    }
+    println!("Loaded calibration from {}", path.display());
+    Ok(())
+}
+
+fn parse<T: FromStr>(value: &str, field: &mut T) -> anyhow::Result<()>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    *field = value.parse()?;
+    Ok(())
+}
@@ -0,0 +1,1462 @@
+use anyhow::Context;
+use jpeg_decoder as jpeg;
+use std::{
+    io,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, TryRecvError},
+        Arc, Mutex, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use v4l::{
+    buffer::{self, Type},
+    capability::Flags,
+    control::{self, Control},
+    frameinterval::FrameIntervalEnum,
+    framesize::FrameSizeEnum,
+    io::traits::CaptureStream,
+    prelude::*,
+    video::Capture,
+    Format, FourCC,
+};
+
+use crate::{
+    color, convert, error::Error, metrics::CaptureStats, source::FrameSource, triple_buffer,
+};
+
+/// The renderer's end of a camera's decoded frames.
+pub type ImageBuffer = triple_buffer::Reader<Frame>;
+
+/// How the bytes of a [`Frame`] are laid out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Interleaved 8-bit RGB.
+    Rgb,
+    /// Interleaved full-range 8-bit YCbCr as JPEGs store it, for the shader to convert.
+    Ycbcr,
+    /// One 8-bit raw sample per pixel, for the shader to demosaic.
+    Bayer(Bayer),
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgb | PixelFormat::Ycbcr => 3,
+            PixelFormat::Bayer(_) => 1,
+        }
+    }
+}
+
+/// A decoded frame as the capture thread publishes it, which describes itself so it can be
+/// used without asking the camera what it's set to.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// Rows top to bottom, without padding; empty until the camera has delivered a frame.
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub pixel_format: PixelFormat,
+    /// When the frame was captured, as far as the source knows.
+    pub timestamp: Instant,
+}
+
+impl Frame {
+    /// A placeholder for a camera that hasn't delivered a frame yet.
+    fn empty(width: u32, height: u32, pixel_format: PixelFormat) -> Self {
+        Frame {
+            data: Vec::new(),
+            width,
+            height,
+            pixel_format,
+            timestamp: Instant::now(),
+        }
+    }
+
+    /// The frame has as many bytes as its size and pixel format call for.
+    pub fn is_complete(&self) -> bool {
+        self.data.len()
+            == self.width as usize * self.height as usize * self.pixel_format.bytes_per_pixel()
+    }
+
+    /// The luma of the pixel at `x`, `y`, or 0 outside the data. A raw Bayer sample stands in
+    /// for it, which is close enough for analysis.
+    pub fn luma(&self, x: usize, y: usize) -> u8 {
+        let index = y * self.width as usize + x;
+        match self.pixel_format {
+            PixelFormat::Bayer(_) => self.data.get(index).copied().unwrap_or(0),
+            pixel_format => match self.data.get(index * 3..index * 3 + 3) {
+                Some(&[luma, ..]) if pixel_format == PixelFormat::Ycbcr => luma,
+                Some(&[r, g, b]) => color::luma([r, g, b]),
+                _ => 0,
+            },
+        }
+    }
+}
+
+/// Called by the capture thread after every frame it publishes, see [`Camera::on_frame`].
+type FrameCallback = Arc<Mutex<Option<Box<dyn FnMut() + Send>>>>;
+
+/// The color filter layout of a raw Bayer sensor, named by its top left 2x2 block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Bayer {
+    Rggb,
+    Grbg,
+    Gbrg,
+    Bggr,
+}
+
+impl Bayer {
+    /// The 8-bit V4L pixel format for the pattern.
+    fn fourcc(self) -> FourCC {
+        FourCC::new(match self {
+            Bayer::Rggb => b"RGGB",
+            Bayer::Grbg => b"GRBG",
+            Bayer::Gbrg => b"GBRG",
+            Bayer::Bggr => b"BA81",
+        })
+    }
+
+    fn from_fourcc(fourcc: FourCC) -> Option<Self> {
+        [Bayer::Rggb, Bayer::Grbg, Bayer::Gbrg, Bayer::Bggr]
+            .into_iter()
+            .find(|bayer| bayer.fourcc() == fourcc)
+    }
+
+    /// Column and row of the red sample in the top left 2x2 block.
+    pub fn red(self) -> (u32, u32) {
+        match self {
+            Bayer::Rggb => (0, 0),
+            Bayer::Grbg => (1, 0),
+            Bayer::Gbrg => (0, 1),
+            Bayer::Bggr => (1, 1),
+        }
+    }
+}
+
+/// How capture buffers are shared with the driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum IoMethod {
+    /// Buffers allocated by the driver and mapped into our memory
+    Mmap,
+    /// Buffers we allocate and the driver writes into
+    Userptr,
+    /// Buffers exported as DMABUF file descriptors; not supported yet, falls back to mmap
+    Dmabuf,
+}
+
+/// How a V4L device is set up and captured from.
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureOptions {
+    pub width: u32,
+    pub height: u32,
+    /// Fail instead of warning when the driver negotiates another resolution.
+    pub strict_resolution: bool,
+    /// Leave MJPEG frames in YCbCr for the shader to convert.
+    pub ycbcr: bool,
+    /// Log the metadata of every dequeued buffer.
+    pub verbose: bool,
+    /// Pin the capture thread to this core.
+    pub core: Option<core_affinity::CoreId>,
+    /// Rotation in degrees to have the device apply if it can, see [`Camera::rotated`].
+    pub roll: f32,
+    /// Capture raw frames with this Bayer pattern, to be demosaiced by the shader. Frames in
+    /// a Bayer format are captured raw anyway, this also picks the format and overrides the
+    /// pattern drivers report.
+    pub bayer: Option<Bayer>,
+    /// Decode only every this many buffers and requeue the others untouched.
+    pub decimate: u32,
+    /// The kind of buffers to stream with, falling back to [`IoMethod::Mmap`].
+    pub io_method: IoMethod,
+    /// How often setting the format and starting the stream are retried while the device is
+    /// busy.
+    pub busy_retry: Retry,
+    /// Capture only this rectangle, as x, y, width and height in pixels from the top left,
+    /// cropping with `VIDIOC_S_SELECTION` or in the shader if the device can't.
+    pub crop: Option<[u32; 4]>,
+}
+
+/// Retries for an operation failing with `EBUSY`, waiting `delay` before the first and twice
+/// as long before each one after.
+///
+/// Since it sleeps, it's only run by capture threads, the [`Watchdog`]'s restarts and [`cam`]
+/// before anything is shown; the render loop only ever asks for a format to change through
+/// [`Camera::set_resolution`].
+#[derive(Debug, Clone, Copy)]
+pub struct Retry {
+    pub attempts: u32,
+    pub delay: Duration,
+}
+
+impl Retry {
+    fn run<T>(self, path: &str, what: &str, mut f: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut delay = self.delay;
+        for attempt in 1..=self.attempts {
+            match f() {
+                Err(error) if error.raw_os_error() == Some(libc::EBUSY) => {
+                    eprintln!(
+                        "WARNING: {path}: device busy, retrying to {what} in {delay:?} \
+                         ({attempt}/{})",
+                        self.attempts
+                    );
+                    thread::sleep(delay);
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+        f()
+    }
+}
+
+/// A running capture thread publishing decoded frames to `buffer`.
+pub struct Camera {
+    pub buffer: ImageBuffer,
+    /// The size of the newest published frame, which changes with [`Camera::set_resolution`].
+    size: Arc<Mutex<(u32, u32)>>,
+    /// The discrete resolutions the device offers for its pixel format, smallest first.
+    pub resolutions: Vec<(u32, u32)>,
+    /// Frames are left in the JPEG's YCbCr for the shader to convert to RGB.
+    pub ycbcr: bool,
+    /// Frames are raw Bayer data with this pattern, one byte per pixel.
+    pub bayer: Option<Bayer>,
+    /// The card name the V4L driver reports, identifying the camera model.
+    pub model: Option<String>,
+    /// The device rotates its frames by the requested roll itself, so the shader doesn't have to.
+    pub rotated: bool,
+    /// The part of every frame to show, as x, y, width and height in fractions of the frame from
+    /// its top left, for crops the device couldn't do itself.
+    pub crop: [f32; 4],
+    pub stats: Arc<Mutex<CaptureStats>>,
+    /// When the newest frame in `buffer` was captured, or `None` before the first one, to
+    /// check for new frames without reading them.
+    pub captured: Arc<Mutex<Option<Instant>>>,
+    /// When the capture thread last published a frame (or was started).
+    last_frame: Arc<Mutex<Instant>>,
+    /// Cleared to ask the capture thread to exit after its current frame.
+    running: Arc<AtomicBool>,
+    /// A resolution the capture thread should switch to before its next frame.
+    resize: Arc<Mutex<Option<(u32, u32)>>>,
+    on_frame: FrameCallback,
+    /// The capture thread, taken by [`Camera::restart`] to wait for it to exit.
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Camera {
+    /// A stand-in camera that only ever shows one already decoded RGB image.
+    pub fn still(data: Vec<u8>, width: u32, height: u32) -> Self {
+        let (_, buffer) = triple_buffer::new(Frame {
+            data,
+            ..Frame::empty(width, height, PixelFormat::Rgb)
+        });
+        Self::with_buffer(buffer, width, height)
+    }
+
+    fn with_buffer(buffer: ImageBuffer, width: u32, height: u32) -> Self {
+        Camera {
+            buffer,
+            size: Arc::new(Mutex::new((width, height))),
+            resolutions: Vec::new(),
+            ycbcr: false,
+            bayer: None,
+            model: None,
+            rotated: false,
+            crop: [0.0, 0.0, 1.0, 1.0],
+            stats: Default::default(),
+            captured: Default::default(),
+            last_frame: Arc::new(Mutex::new(Instant::now())),
+            running: Arc::new(AtomicBool::new(false)),
+            resize: Default::default(),
+            on_frame: Default::default(),
+            thread: None,
+        }
+    }
+
+    /// Publishes the frames of a non-V4L source from a thread of its own.
+    pub fn from_source(mut source: impl FrameSource, name: &str, width: u32, height: u32) -> Self {
+        let (mut writer, buffer) =
+            triple_buffer::new(Frame::empty(width, height, PixelFormat::Rgb));
+        let mut camera = Camera::with_buffer(buffer, width, height);
+        camera.running.store(true, Ordering::Relaxed);
+        camera.thread = Some(thread::spawn({
+            let stats = Arc::clone(&camera.stats);
+            let captured = Arc::clone(&camera.captured);
+            let last_frame = Arc::clone(&camera.last_frame);
+            let running = Arc::clone(&camera.running);
+            let resize = Arc::clone(&camera.resize);
+            let size = Arc::clone(&camera.size);
+            let on_frame = Arc::clone(&camera.on_frame);
+            let (mut width, mut height) = camera.size();
+            let name = name.to_owned();
+            move || {
+                while running.load(Ordering::Relaxed) {
+                    if let Some((new_width, new_height)) = resize.lock().unwrap().take() {
+                        match source.set_resolution(new_width, new_height) {
+                            Ok(()) => {
+                                println!("\n{name}: now at {new_width}x{new_height}");
+                                (width, height) = (new_width, new_height);
+                            }
+                            Err(error) => {
+                                eprintln!("\n{name}: couldn't switch resolution: {error:#}")
+                            }
+                        }
+                    }
+                    match source.next_frame() {
+                        Ok(data) => {
+                            stats.lock().unwrap().published();
+                            let frame = Frame {
+                                data,
+                                ..Frame::empty(width, height, PixelFormat::Rgb)
+                            };
+                            let timestamp = frame.timestamp;
+                            publish(&mut writer, &size, frame);
+                            *captured.lock().unwrap() = Some(timestamp);
+                            *last_frame.lock().unwrap() = Instant::now();
+                            notify(&on_frame);
+                        }
+                        Err(error) => {
+                            eprintln!("\n{name}: {error:#}");
+                            stats.lock().unwrap().failed();
+                        }
+                    }
+                }
+            }
+        }));
+        camera
+    }
+
+    /// The size of the newest frame, for laying out the window; read frames with
+    /// [`Camera::frame`], which carry their own.
+    pub fn size(&self) -> (u32, u32) {
+        *self.size.lock().unwrap()
+    }
+
+    /// The size of the part of the newest frame that's shown, see [`Camera::crop`].
+    pub fn cropped_size(&self) -> (u32, u32) {
+        let (width, height) = self.size();
+        let [_, _, crop_width, crop_height] = self.crop;
+        (
+            (width as f32 * crop_width).round() as u32,
+            (height as f32 * crop_height).round() as u32,
+        )
+    }
+
+    /// The newest frame.
+    pub fn frame(&mut self) -> &Frame {
+        self.buffer.read()
+    }
+
+    /// Has the capture thread call `callback` after each frame it publishes, e.g. to wake up
+    /// the event loop, also after a [`Watchdog`] restarts it.
+    pub fn on_frame(&self, callback: impl FnMut() + Send + 'static) {
+        *self.on_frame.lock().unwrap() = Some(Box::new(callback));
+    }
+
+    /// Asks the capture thread to switch to another resolution, which takes effect from the
+    /// next frame on.
+    ///
+    /// V4L devices stop streaming, renegotiate the format and start again on the capture
+    /// thread, retrying while they're busy, since not all drivers can change it while buffers
+    /// are allocated; other sources switch however they can, if they can.
+    pub fn set_resolution(&self, width: u32, height: u32) {
+        *self.resize.lock().unwrap() = Some((width, height));
+    }
+
+    /// Blocks until the first frame has been published, or `timeout` has passed.
+    pub fn wait_for_frame(&self, timeout: Duration) -> bool {
+        wait_until(timeout, || self.captured.lock().unwrap().is_some())
+    }
+
+    /// How long it's been since the capture thread produced a frame.
+    pub fn stalled_for(&self) -> Duration {
+        self.last_frame.lock().unwrap().elapsed()
+    }
+
+    /// Reopens the V4L device at `path` in the background, for a camera sharing this one's
+    /// statistics and callback that starts out showing its last frame.
+    fn restart(&mut self, path: &str, options: CaptureOptions) -> Restart {
+        let reopen_path = path.to_owned();
+        let stats = Arc::clone(&self.stats);
+        let captured = Arc::clone(&self.captured);
+        let on_frame = Arc::clone(&self.on_frame);
+        self.restart_with(path, move |last| {
+            open(&reopen_path, options, Some(last), stats, captured, on_frame)
+        })
+    }
+
+    /// Tells the capture thread to exit, then waits for it and calls `reopen` with the last
+    /// frame on a thread of its own, which hands over the new camera once that's streaming.
+    ///
+    /// The old capture thread can't be interrupted while it's blocked in the driver, so if it
+    /// hasn't exited after [`RESTART_TIMEOUT`] the device is reopened anyway, and setting it up
+    /// is retried for as long as the old stream keeps it busy.
+    fn restart_with(
+        &mut self,
+        name: &str,
+        reopen: impl FnOnce(Frame) -> Result<Camera, Error> + Send + 'static,
+    ) -> Restart {
+        self.running.store(false, Ordering::Relaxed);
+        self.stats.lock().unwrap().restart();
+        let last = self.buffer.read().clone();
+        let old = self.thread.take();
+        let name = name.to_owned();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            if let Some(old) = old {
+                if wait_until(RESTART_TIMEOUT, || old.is_finished()) {
+                    // a panic has been printed already
+                    old.join().ok();
+                } else {
+                    eprintln!(
+                        "\nWARNING: {name}: the stalled capture didn't exit, reopening anyway"
+                    );
+                }
+            }
+            let camera = reopen(last).inspect(|camera| {
+                let before = *camera.captured.lock().unwrap();
+                // or until it's clear it won't, so the watchdog can try again
+                wait_until(RESTART_TIMEOUT, || {
+                    *camera.captured.lock().unwrap() != before
+                        || camera.thread.as_ref().map_or(true, JoinHandle::is_finished)
+                });
+            });
+            // the watchdog may be gone by now
+            sender.send(camera).ok();
+        });
+        receiver
+    }
+}
+
+/// How long a restart waits for the stalled capture thread to exit, and for the new one's
+/// first frame.
+const RESTART_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Where [`Camera::restart`] hands over the reopened camera.
+type Restart = mpsc::Receiver<Result<Camera, Error>>;
+
+/// Restarts a camera's capture once it hasn't produced a frame for a while, from another
+/// thread, so the render loop keeps showing the last frame instead of waiting on the device.
+pub struct Watchdog {
+    name: String,
+    timeout: Duration,
+    reopen: Box<dyn FnMut(&mut Camera) -> Restart>,
+    /// The restart in progress.
+    restart: Option<Restart>,
+}
+
+impl Watchdog {
+    /// Watches a camera opened from the V4L device at `path` with `options`.
+    pub fn new(path: &str, options: CaptureOptions, timeout: Duration) -> Self {
+        let reopen_path = path.to_owned();
+        Watchdog {
+            name: path.to_owned(),
+            timeout,
+            reopen: Box::new(move |camera| camera.restart(&reopen_path, options)),
+            restart: None,
+        }
+    }
+
+    /// Restarts `camera` if it has stalled, or replaces it with the restarted one once that's
+    /// streaming; never blocks.
+    pub fn check(&mut self, camera: &mut Camera) {
+        match self.restart.as_ref().map(Restart::try_recv) {
+            None if camera.stalled_for() > self.timeout => {
+                eprintln!("\n{} stalled, restarting capture", self.name);
+                self.restart = Some((self.reopen)(camera));
+            }
+            None | Some(Err(TryRecvError::Empty)) => {}
+            Some(Ok(Ok(restarted))) => {
+                self.restart = None;
+                *camera = restarted;
+            }
+            Some(result) => {
+                if let Ok(Err(error)) = result {
+                    eprintln!("\n{}: failed to restart: {error:#}", self.name);
+                }
+                self.restart = None;
+                // don't retry again before another full timeout has passed
+                *camera.last_frame.lock().unwrap() = Instant::now();
+            }
+        }
+    }
+}
+
+/// Polls `done` until it's true or `timeout` has passed, and returns whether it came true.
+fn wait_until(timeout: Duration, mut done: impl FnMut() -> bool) -> bool {
+    let start = Instant::now();
+    while !done() {
+        if start.elapsed() > timeout {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    true
+}
+
+fn publish(writer: &mut triple_buffer::Writer<Frame>, size: &Mutex<(u32, u32)>, frame: Frame) {
+    *size.lock().unwrap() = (frame.width, frame.height);
+    writer.publish(frame);
+}
+
+fn notify(on_frame: &Mutex<Option<Box<dyn FnMut() + Send>>>) {
+    if let Some(callback) = &mut *on_frame.lock().unwrap() {
+        callback();
+    }
+}
+
+/// A camera switched to capturing only when triggered, for rigs whose cameras should expose
+/// both eyes at the same instant.
+///
+/// V4L has no standard controls for this, so they're looked up by the names drivers that
+/// support it tend to use. The camera goes back to free-running when this is dropped.
+pub struct Trigger {
+    dev: Device,
+    /// ID and type of the control switching between free-running and triggered capture.
+    mode: (u32, control::Type),
+    software_trigger: u32,
+}
+
+impl Trigger {
+    pub fn enable(path: &str) -> anyhow::Result<Self> {
+        let dev = Device::with_path(path)?;
+        let controls = dev.query_controls()?;
+        let find = |words: &[&str]| {
+            controls.iter().find(|control| {
+                let name = control.name.to_lowercase();
+                words.iter().all(|word| name.contains(word))
+            })
+        };
+        let mode = find(&["trigger", "mode"])
+            .with_context(|| format!("{path} has no trigger mode control"))?;
+        let software_trigger = find(&["software", "trigger"])
+            .filter(|control| control.typ == control::Type::Button)
+            .with_context(|| format!("{path} has no software trigger control"))?;
+        let trigger = Trigger {
+            mode: (mode.id, mode.typ),
+            software_trigger: software_trigger.id,
+            dev,
+        };
+        trigger
+            .set_mode(true)
+            .with_context(|| format!("Couldn't enable {}", mode.name))?;
+        Ok(trigger)
+    }
+
+    fn set_mode(&self, triggered: bool) -> std::io::Result<()> {
+        let (id, typ) = self.mode;
+        self.dev.set_control(Control {
+            id,
+            value: match typ {
+                control::Type::Boolean => control::Value::Boolean(triggered),
+                _ => control::Value::Integer(triggered.into()),
+            },
+        })
+    }
+
+    /// Makes the camera capture one frame.
+    pub fn fire(&self) -> std::io::Result<()> {
+        self.dev.set_control(Control {
+            id: self.software_trigger,
+            value: control::Value::None,
+        })
+    }
+}
+
+/// Fires all `triggers`, for one frame from each of their cameras captured at the same instant.
+pub fn fire(triggers: &[Trigger]) {
+    for trigger in triggers {
+        if let Err(error) = trigger.fire() {
+            eprintln!("\nfailed to trigger capture: {error}");
+        }
+    }
+}
+
+impl Drop for Trigger {
+    fn drop(&mut self) {
+        if let Err(error) = self.set_mode(false) {
+            eprintln!("WARNING: couldn't switch a camera back to free-running: {error}");
+        }
+    }
+}
+
+/// A camera's autofocus region of interest, through the UVC 1.5 controls `uvcvideo` exposes.
+pub struct FocusRegion {
+    dev: Device,
+}
+
+impl FocusRegion {
+    const RECT: u32 = 0x009a_1901;
+    const AUTO: u32 = 0x009a_1902;
+    /// The `AUTO` control's bit for having autofocus use the region.
+    const AUTO_FOCUS: i64 = 1 << 3;
+
+    pub fn enable(path: &str) -> anyhow::Result<Self> {
+        let dev = Device::with_path(path)?;
+        let controls = dev.query_controls()?;
+        anyhow::ensure!(
+            [Self::RECT, Self::AUTO]
+                .iter()
+                .all(|&id| controls.iter().any(|control| control.id == id)),
+            "{path} has no autofocus region of interest controls"
+        );
+        dev.set_control(Control {
+            id: Self::AUTO,
+            value: control::Value::Integer(Self::AUTO_FOCUS),
+        })
+        .with_context(|| format!("Couldn't have {path} autofocus on a region"))?;
+        Ok(FocusRegion { dev })
+    }
+
+    /// Has autofocus look at a tenth of the frame around `(x, y)`, from 0 to 1 with the origin
+    /// at the top left of the camera's frames.
+    pub fn focus_on(&self, x: f32, y: f32) -> std::io::Result<()> {
+        let format = self.dev.format()?;
+        let (width, height) = ((format.width / 10).max(1), (format.height / 10).max(1));
+        let corner = |center: f32, size: u32, frame: u32| {
+            ((center * frame as f32) as i32 - size as i32 / 2).clamp(0, (frame - size) as i32)
+        };
+        // struct v4l2_rect
+        let mut rect = Vec::with_capacity(16);
+        rect.extend(corner(x, width, format.width).to_ne_bytes());
+        rect.extend(corner(y, height, format.height).to_ne_bytes());
+        rect.extend(width.to_ne_bytes());
+        rect.extend(height.to_ne_bytes());
+        self.dev.set_controls(vec![Control {
+            id: Self::RECT,
+            value: control::Value::CompoundPtr(rect),
+        }])
+    }
+}
+
+/// Path and card name of every V4L device that can capture video, lowest index first.
+pub fn capture_devices() -> Vec<(String, String)> {
+    let mut nodes = v4l::context::enum_devices();
+    nodes.sort_by_key(|node| node.index());
+    nodes
+        .iter()
+        .filter_map(|node| {
+            let caps = Device::with_path(node.path()).ok()?.query_caps().ok()?;
+            // metadata nodes of UVC cameras don't have this capability
+            caps.capabilities
+                .contains(Flags::VIDEO_CAPTURE)
+                .then(|| (node.path().display().to_string(), caps.card))
+        })
+        .collect()
+}
+
+/// Resolves `name:<card name>` and `serial:<USB serial number>` to the capture device they
+/// identify, so configs keep working when device paths shuffle; anything else is a path.
+pub fn resolve(device: &str) -> anyhow::Result<String> {
+    let serial = |path: &str| {
+        // the video node's device is the USB interface, the serial is on its parent
+        let node = Path::new(path).file_name()?.to_str()?;
+        let serial =
+            std::fs::read_to_string(format!("/sys/class/video4linux/{node}/device/../serial"));
+        Some(serial.ok()?.trim().to_owned())
+    };
+    let matches: Vec<_> = if let Some(name) = device.strip_prefix("name:") {
+        capture_devices()
+            .into_iter()
+            .filter(|(_, card)| card == name)
+            .map(|(path, _)| path)
+            .collect()
+    } else if let Some(wanted) = device.strip_prefix("serial:") {
+        capture_devices()
+            .into_iter()
+            .map(|(path, _)| path)
+            .filter(|path| serial(path).as_deref() == Some(wanted))
+            .collect()
+    } else {
+        return Ok(device.to_owned());
+    };
+    match &matches[..] {
+        [path] => {
+            println!("{device} is {path}");
+            Ok(path.clone())
+        }
+        [] => anyhow::bail!("no capture device matches {device}"),
+        paths => anyhow::bail!(
+            "{device} matches several capture devices: {}",
+            paths.join(", ")
+        ),
+    }
+}
+
+/// The two capture nodes of the stereo camera `base` is one of, lower-numbered first.
+///
+/// Such cameras show up as one node per sensor with the same bus info. Some only start both
+/// streams when the lower-numbered node, which is the left sensor on the ones seen so far,
+/// starts first.
+pub fn stereo_pair(base: &str) -> anyhow::Result<[String; 2]> {
+    let bus = |path: &str| Some(Device::with_path(path).ok()?.query_caps().ok()?.bus);
+    let base_bus = bus(base).with_context(|| format!("Couldn't query {base}"))?;
+    let devices = capture_devices();
+    let nodes: Vec<_> = devices
+        .iter()
+        .map(|(path, _)| path)
+        .filter(|path| bus(path).as_ref() == Some(&base_bus))
+        .collect();
+    match nodes[..] {
+        [left, right] => Ok([left.clone(), right.clone()]),
+        _ => anyhow::bail!(
+            "expected two capture nodes on {base}'s bus {base_bus}, found {}",
+            nodes.len()
+        ),
+    }
+}
+
+/// Sizes the rayon pool `jpeg-decoder` splits each frame across, shared by all capture threads.
+pub fn set_decode_threads(threads: usize) -> anyhow::Result<()> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(|index| format!("decode {index}"))
+        .build_global()
+        .context("Couldn't start the decode threads")
+}
+
+/// Decodes the JPEG at `path` `iterations` times like the capture threads decode MJPEG frames,
+/// alone and then on two threads at once like two cameras, and prints how long that took.
+pub fn benchmark_decode(path: &Path, iterations: u32, ycbcr: bool) -> anyhow::Result<()> {
+    let data = std::fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    let mut decoder = jpeg::Decoder::new(&data[..]);
+    decoder.read_info().context("Couldn't read JPEG header")?;
+    let info = decoder.info().context("JPEG has no frame header")?;
+    println!(
+        "Decoding {}x{} {:?} JPEG {iterations} times{}",
+        info.width,
+        info.height,
+        info.pixel_format,
+        if ycbcr { " to YCbCr" } else { "" }
+    );
+
+    // once up front, so errors show before timing and the first run's allocations don't count
+    decode_jpeg(&data, ycbcr)?;
+    let start = Instant::now();
+    for _ in 0..iterations.max(1) {
+        decode_jpeg(&data, ycbcr)?;
+    }
+    let per_frame = start.elapsed() / iterations.max(1);
+    println!(
+        "{:.2} ms per frame, {:.1} fps on {} decode threads",
+        per_frame.as_secs_f64() * 1000.0,
+        1.0 / per_frame.as_secs_f64(),
+        rayon::current_num_threads()
+    );
+
+    let start = Instant::now();
+    std::thread::scope(|scope| {
+        let cameras = [(); 2].map(|()| {
+            scope.spawn(|| {
+                (0..iterations.max(1)).try_for_each(|_| decode_jpeg(&data, ycbcr).map(drop))
+            })
+        });
+        cameras
+            .into_iter()
+            .try_for_each(|camera| camera.join().unwrap())
+    })?;
+    let per_frame = start.elapsed() / iterations.max(1);
+    println!(
+        "{:.2} ms per frame pair, {:.1} fps per camera with two cameras",
+        per_frame.as_secs_f64() * 1000.0,
+        1.0 / per_frame.as_secs_f64()
+    );
+    Ok(())
+}
+
+/// Prints everything the device at `path` supports: formats, their resolutions and frame
+/// rates, and all controls with their ranges.
+pub fn print_capabilities(path: &str) -> anyhow::Result<()> {
+    let dev = Device::with_path(path).with_context(|| format!("Couldn't open {path}"))?;
+    let caps = dev.query_caps()?;
+    println!("{path}: {} ({}, {})", caps.card, caps.driver, caps.bus);
+
+    println!("formats:");
+    for format in dev.enum_formats()? {
+        println!("  {} ({})", format.fourcc, format.description);
+        for size in dev.enum_framesizes(format.fourcc)? {
+            let FrameSizeEnum::Discrete(size) = size.size else {
+                println!("    {}", size.size);
+                continue;
+            };
+            let intervals = dev.enum_frameintervals(format.fourcc, size.width, size.height)?;
+            let rates: Vec<_> = intervals
+                .iter()
+                .map(|interval| match &interval.interval {
+                    FrameIntervalEnum::Discrete(interval) => {
+                        let fps = interval.denominator as f32 / interval.numerator as f32;
+                        let fps = format!("{fps:.2}");
+                        format!("{} fps", fps.trim_end_matches('0').trim_end_matches('.'))
+                    }
+                    stepwise => stepwise.to_string(),
+                })
+                .collect();
+            println!("    {}x{}: {}", size.width, size.height, rates.join(", "));
+        }
+    }
+
+    println!("controls:");
+    for control in dev.query_controls()? {
+        match control.typ {
+            control::Type::CtrlClass => println!("  {}", control.name),
+            control::Type::Integer | control::Type::Integer64 => println!(
+                "    {} ({}): {}..{} step {}, default {}",
+                control.name,
+                control.typ,
+                control.minimum,
+                control.maximum,
+                control.step,
+                control.default
+            ),
+            _ => println!(
+                "    {} ({}): default {}",
+                control.name, control.typ, control.default
+            ),
+        }
+        for (value, item) in control.items.iter().flatten() {
+            println!("      {value}: {item}");
+        }
+    }
+    Ok(())
+}
+
+/// Opens the V4L device at `path` and starts capturing from it.
+pub fn cam(path: &str, options: CaptureOptions) -> Result<Camera, Error> {
+    open(
+        path,
+        options,
+        None,
+        Default::default(),
+        Default::default(),
+        Default::default(),
+    )
+}
+
+/// Opens the V4L device at `path` and negotiates the format like [`cam`], without capturing.
+pub fn probe(path: &str, options: CaptureOptions) -> Result<(), Error> {
+    set_up(path, options).map(drop)
+}
+
+/// A device whose format has been negotiated.
+struct Setup {
+    dev: Device,
+    model: String,
+    format: Format,
+    rotated: bool,
+    /// The crop left to the shader, in fractions of the frame.
+    crop: [f32; 4],
+    resolutions: Vec<(u32, u32)>,
+}
+
+fn set_up(path: &str, options: CaptureOptions) -> Result<Setup, Error> {
+    println!("Using device: {}\n", path);
+    let CaptureOptions { width, height, .. } = options;
+
+    let dev = Device::with_path(path).map_err(|source| Error::DeviceOpen {
+        path: path.to_owned(),
+        source,
+    })?;
+
+    let model = dev
+        .query_caps()
+        .map_err(|source| Error::DeviceOpen {
+            path: path.to_owned(),
+            source,
+        })?
+        .card;
+
+    // before setting the format, since rotating by 90° may swap width and height
+    let rotated = rotate_in_hardware(&dev, options.roll);
+    if options.roll != 0.0 {
+        let by = if rotated { "the camera" } else { "the shader" };
+        println!("{path}: rotating by {}° in {by}", options.roll);
+    }
+
+    // before setting the format too, which is then the size of the crop so it isn't scaled
+    let hardware_crop = options
+        .crop
+        .and_then(|crop| match crop_in_hardware(&dev, crop) {
+            Ok([x, y, width, height]) => {
+                println!("{path}: cropping to {width}x{height} at {x},{y} in the camera");
+                Some((width, height))
+            }
+            Err(error) => {
+                eprintln!(
+                    "WARNING: {path}: the camera can't crop ({error}), cropping in the shader"
+                );
+                None
+            }
+        });
+
+    let fourcc = options.bayer.map_or(FourCC::new(b"MJPG"), Bayer::fourcc);
+    let strict = options.strict_resolution;
+    let format = negotiate(
+        &dev,
+        path,
+        fourcc,
+        hardware_crop.unwrap_or((width, height)),
+        strict,
+        options.busy_retry,
+    )?;
+    let crop = match options.crop {
+        Some([x, y, crop_width, crop_height]) if hardware_crop.is_none() => {
+            let (width, height) = (format.width, format.height);
+            if crop_width == 0
+                || crop_height == 0
+                || x.saturating_add(crop_width) > width
+                || y.saturating_add(crop_height) > height
+            {
+                return Err(Error::FormatNegotiation {
+                    path: path.to_owned(),
+                    message: format!(
+                        "the crop {crop_width}x{crop_height} at {x},{y} doesn't fit into the \
+                         {width}x{height} frames"
+                    ),
+                });
+            }
+            let (width, height) = (width as f32, height as f32);
+            [
+                x as f32 / width,
+                y as f32 / height,
+                crop_width as f32 / width,
+                crop_height as f32 / height,
+            ]
+        }
+        _ => [0.0, 0.0, 1.0, 1.0],
+    };
+    // some drivers deliver Bayer data as plain grayscale
+    if options.bayer.is_some()
+        && Bayer::from_fourcc(format.fourcc).is_none()
+        && format.fourcc != FourCC::new(b"GREY")
+    {
+        return Err(Error::FormatNegotiation {
+            path: path.to_owned(),
+            message: format!("got {} instead of 8-bit raw Bayer frames", format.fourcc),
+        });
+    }
+    let failed = |what, error| Error::FormatNegotiation {
+        path: path.to_owned(),
+        message: format!("couldn't {what}: {error}"),
+    };
+    let params = dev
+        .params()
+        .map_err(|error| failed("get parameters", error))?;
+    let mut resolutions: Vec<_> = dev
+        .enum_framesizes(format.fourcc)
+        .map_err(|error| failed("list resolutions", error))?
+        .into_iter()
+        .filter_map(|size| match size.size {
+            FrameSizeEnum::Discrete(size) => Some((size.width, size.height)),
+            // stepwise sizes can't be stepped through one by one in any useful way
+            FrameSizeEnum::Stepwise(_) => None,
+        })
+        .collect();
+    resolutions.sort_by_key(|&(width, height)| width * height);
+
+    println!("Active format:\n{}", format);
+    println!("Active parameters:\n{}", params);
+
+    Ok(Setup {
+        dev,
+        model,
+        format,
+        rotated,
+        crop,
+        resolutions,
+    })
+}
+
+fn open(
+    path: &str,
+    options: CaptureOptions,
+    initial: Option<Frame>,
+    stats: Arc<Mutex<CaptureStats>>,
+    captured: Arc<Mutex<Option<Instant>>>,
+    on_frame: FrameCallback,
+) -> Result<Camera, Error> {
+    let Setup {
+        dev,
+        model,
+        format,
+        rotated,
+        crop,
+        resolutions,
+    } = set_up(path, options)?;
+    let dev = RwLock::new(dev);
+
+    let last_frame = Arc::new(Mutex::new(Instant::now()));
+    let running = Arc::new(AtomicBool::new(true));
+    let resize: Arc<Mutex<Option<(u32, u32)>>> = Default::default();
+    let size = Arc::new(Mutex::new((format.width, format.height)));
+    // uncompressed formats are converted to RGB on the CPU
+    let ycbcr = options.ycbcr && format.fourcc == FourCC::new(b"MJPG");
+    let bayer = options.bayer.or(Bayer::from_fourcc(format.fourcc));
+    if options.io_method == IoMethod::Dmabuf {
+        eprintln!("WARNING: {path}: DMABUF capture isn't supported, using mmap buffers");
+    }
+
+    let pixel_format = match bayer {
+        Some(bayer) => PixelFormat::Bayer(bayer),
+        None if ycbcr => PixelFormat::Ycbcr,
+        None => PixelFormat::Rgb,
+    };
+    let initial =
+        initial.unwrap_or_else(|| Frame::empty(format.width, format.height, pixel_format));
+    let (mut writer, buffer) = triple_buffer::new(initial);
+    let thread = thread::spawn({
+        let last_frame = Arc::clone(&last_frame);
+        let stats = Arc::clone(&stats);
+        let captured = Arc::clone(&captured);
+        let running = Arc::clone(&running);
+        let resize = Arc::clone(&resize);
+        let size = Arc::clone(&size);
+        let on_frame = Arc::clone(&on_frame);
+        let path = path.to_owned();
+        let mut format = format;
+        move || {
+            if let Some(core) = options.core {
+                if !core_affinity::set_for_current(core) {
+                    eprintln!(
+                        "WARNING: {path}: couldn't pin capture thread to core {}",
+                        core.id
+                    );
+                }
+            }
+            let dev = dev.read().unwrap();
+            let Some(mut stream) = start_stream(&dev, &path, options) else {
+                return;
+            };
+
+            let mut last_sequence = None;
+            let mut skipped = 0;
+            loop {
+                if let Some((width, height)) = resize.lock().unwrap().take() {
+                    // the format can't change while buffers are allocated
+                    drop(stream);
+                    let retry = options.busy_retry;
+                    match negotiate(&dev, &path, format.fourcc, (width, height), false, retry) {
+                        Ok(negotiated) => {
+                            let (got_width, got_height) = (negotiated.width, negotiated.height);
+                            if (got_width, got_height) != (width, height) {
+                                eprintln!(
+                                    "\nWARNING: {path}: {width}x{height} was rejected, \
+                                     the driver picked {got_width}x{got_height}"
+                                );
+                            }
+                            println!("\n{path}: now at {got_width}x{got_height}");
+                            // the frames captured from now on carry it
+                            format = negotiated;
+                        }
+                        Err(error) => eprintln!("\n{path}: couldn't switch resolution: {error:#}"),
+                    }
+                    let Some(restarted) = start_stream(&dev, &path, options) else {
+                        return;
+                    };
+                    stream = restarted;
+                    stats.lock().unwrap().restart();
+                }
+                let channels = if bayer.is_some() { 1 } else { 3 };
+                let frame_len = format.width as usize * format.height as usize * channels;
+                let (buf, capture_time) = match stream.next() {
+                    Ok((buf, meta)) => {
+                        if options.verbose {
+                            let dropped = last_sequence.map_or(0, |last: u32| {
+                                meta.sequence.wrapping_sub(last).saturating_sub(1)
+                            });
+                            println!(
+                                "{path}: buffer {} at {}, {} bytes, flags {}{}",
+                                meta.sequence,
+                                meta.timestamp,
+                                meta.bytesused,
+                                meta.flags,
+                                match dropped {
+                                    0 => String::new(),
+                                    dropped => format!(", driver dropped {dropped} frames"),
+                                }
+                            );
+                            last_sequence = Some(meta.sequence);
+                        }
+                        stats.lock().unwrap().buffer(meta.sequence);
+                        let capture_time =
+                            if meta.flags.contains(buffer::Flags::TIMESTAMP_MONOTONIC) {
+                                monotonic_instant(meta.timestamp)
+                            } else {
+                                Instant::now()
+                            };
+                        (buf, capture_time)
+                    }
+                    Err(error) => {
+                        eprintln!("{path}: failed to capture frame: {error}");
+                        return;
+                    }
+                };
+                if !running.load(Ordering::Relaxed) {
+                    return;
+                }
+                if skipped + 1 < options.decimate {
+                    skipped += 1;
+                    // the stream is alive, so don't let the watchdog restart it
+                    *last_frame.lock().unwrap() = Instant::now();
+                    continue;
+                }
+                skipped = 0;
+                let decode_start = Instant::now();
+                let data = match &format.fourcc.repr {
+                    _ if bayer.is_some() => match buf.get(..frame_len) {
+                        Some(raw) => raw.to_vec(),
+                        None => {
+                            eprintln!("{path}: got {} bytes instead of {frame_len}", buf.len());
+                            stats.lock().unwrap().failed();
+                            continue;
+                        }
+                    },
+                    b"RGB3" => buf.to_vec(),
+                    b"BGR3" => convert::bgr_to_rgb(buf),
+                    b"YUYV" => convert::yuyv_to_rgb(buf),
+                    b"NV12" => convert::nv12_to_rgb(buf, format.width, format.height),
+                    b"MJPG" => match decode_jpeg(buf, ycbcr) {
+                        Ok(data) if data.len() == frame_len => data,
+                        Ok(data) => {
+                            eprintln!(
+                                "{path}: decoded {} bytes instead of {frame_len}",
+                                data.len()
+                            );
+                            stats.lock().unwrap().failed();
+                            continue;
+                        }
+                        Err(error) => {
+                            eprintln!("{path}: {error}");
+                            stats.lock().unwrap().failed();
+                            continue;
+                        }
+                    },
+                    _ => {
+                        eprintln!("invalid buffer pixelformat");
+                        stats.lock().unwrap().failed();
+                        continue;
+                    }
+                };
+                stats.lock().unwrap().decoded(decode_start.elapsed());
+                let frame = Frame {
+                    data,
+                    width: format.width,
+                    height: format.height,
+                    pixel_format,
+                    timestamp: capture_time,
+                };
+                publish(&mut writer, &size, frame);
+                *captured.lock().unwrap() = Some(capture_time);
+                *last_frame.lock().unwrap() = Instant::now();
+                notify(&on_frame);
+            }
+        }
+    });
+
+    Ok(Camera {
+        buffer,
+        size,
+        resolutions,
+        ycbcr,
+        bayer,
+        model: Some(model),
+        rotated,
+        crop,
+        stats,
+        captured,
+        last_frame,
+        running,
+        resize,
+        on_frame,
+        thread: Some(thread),
+    })
+}
+
+/// Sets `fourcc` at the given resolution and returns the format the driver settled on, which
+/// may have another resolution unless `strict`, or another pixel format.
+fn negotiate(
+    dev: &Device,
+    path: &str,
+    fourcc: FourCC,
+    (width, height): (u32, u32),
+    strict: bool,
+    busy_retry: Retry,
+) -> Result<Format, Error> {
+    let failed = |message| Error::FormatNegotiation {
+        path: path.to_owned(),
+        message,
+    };
+    let format = busy_retry
+        .run(path, "set the format", || {
+            dev.set_format(&Format::new(width, height, fourcc))
+        })
+        .and_then(|_| dev.format())
+        .map_err(|error| failed(format!("Couldn't set format: {error}")))?;
+
+    if (format.width, format.height) != (width, height) {
+        let supported = dev
+            .enum_framesizes(format.fourcc)
+            .map_err(|error| failed(format!("couldn't list resolutions: {error}")))?
+            .into_iter()
+            .map(|size| format!("  {}", size.size))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let message = format!(
+            "negotiated {}x{} instead of the requested {width}x{height}; \
+             supported {} resolutions:\n{supported}",
+            format.width, format.height, format.fourcc
+        );
+        if strict {
+            return Err(failed(message));
+        }
+        eprintln!("WARNING: {path}: {message}");
+    }
+    Ok(format)
+}
+
+fn start_stream<'a>(dev: &'a Device, path: &str, options: CaptureOptions) -> Option<Stream<'a>> {
+    let buffer_count = 2;
+    let mmap = || MmapStream::with_buffers(dev, Type::VideoCapture, buffer_count).map(Stream::Mmap);
+    let stream = options
+        .busy_retry
+        .run(path, "start streaming", || match options.io_method {
+            IoMethod::Userptr => UserptrStream::with_buffers(dev, Type::VideoCapture, buffer_count)
+                .map(Stream::Userptr)
+                .or_else(|error| {
+                    eprintln!("WARNING: {path}: no user pointer buffers ({error}), using mmap");
+                    mmap()
+                }),
+            IoMethod::Mmap | IoMethod::Dmabuf => mmap(),
+        });
+    match stream {
+        Ok(stream) => Some(stream),
+        Err(error) => {
+            eprintln!("{path}: failed to start streaming: {error}");
+            None
+        }
+    }
+}
+
+/// A capture stream with either kind of buffers.
+enum Stream<'a> {
+    Mmap(MmapStream<'a>),
+    Userptr(UserptrStream),
+}
+
+impl Stream<'_> {
+    fn next(&mut self) -> std::io::Result<(&[u8], &buffer::Metadata)> {
+        match self {
+            Stream::Mmap(stream) => stream.next(),
+            Stream::Userptr(stream) => stream.next(),
+        }
+    }
+}
+
+/// Tries to have the device rotate its frames with `V4L2_CID_ROTATE`, which only supports
+/// multiples of 90°.
+fn rotate_in_hardware(dev: &Device, roll: f32) -> bool {
+    const V4L2_CID_ROTATE: u32 = 0x0098_0922;
+    if roll == 0.0 || roll % 90.0 != 0.0 {
+        return false;
+    }
+    dev.set_control(Control {
+        id: V4L2_CID_ROTATE,
+        value: control::Value::Integer((roll as i64).rem_euclid(360)),
+    })
+    .is_ok()
+}
+
+/// Sets the device's crop rectangle with `VIDIOC_S_SELECTION`, returning the one the driver
+/// settled on, which may be aligned or clamped to what the sensor can do.
+fn crop_in_hardware(dev: &Device, [x, y, width, height]: [u32; 4]) -> io::Result<[u32; 4]> {
+    /// `_IOWR('V', 95, struct v4l2_selection)`.
+    const VIDIOC_S_SELECTION: v4l::v4l2::vidioc::_IOC_TYPE = 0xC040_565F;
+    const V4L2_BUF_TYPE_VIDEO_CAPTURE: u32 = 1;
+    const V4L2_SEL_TGT_CROP: u32 = 0;
+
+    let mut selection = v4l::v4l_sys::v4l2_selection {
+        type_: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+        target: V4L2_SEL_TGT_CROP,
+        r: v4l::v4l_sys::v4l2_rect {
+            left: x as i32,
+            top: y as i32,
+            width,
+            height,
+        },
+        // SAFETY: the rest are flags and reserved fields, all zero by default
+        ..unsafe { std::mem::zeroed() }
+    };
+    // SAFETY: the request takes a `v4l2_selection`, which the driver adjusts in place
+    unsafe {
+        v4l::v4l2::ioctl(
+            dev.handle().fd(),
+            VIDIOC_S_SELECTION,
+            &mut selection as *mut _ as *mut std::os::raw::c_void,
+        )?;
+    }
+    let rect = selection.r;
+    Ok([rect.left as u32, rect.top as u32, rect.width, rect.height])
+}
+
+/// Converts a driver timestamp taken from `CLOCK_MONOTONIC`, which `Instant` is based on too but
+/// can't be built from.
+fn monotonic_instant(timestamp: v4l::Timestamp) -> Instant {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: clock_gettime only writes the current time into the timespec it's given
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    let now = Duration::new(now.tv_sec as u64, now.tv_nsec as u32);
+    let captured = Duration::new(timestamp.sec as u64, timestamp.usec as u32 * 1000);
+    Instant::now() - now.saturating_sub(captured)
+}
+
+/// Decodes a JPEG into interleaved RGB, or leaves it in YCbCr with `ycbcr`.
+fn decode_jpeg(data: &[u8], ycbcr: bool) -> Result<Vec<u8>, Error> {
+    let mut decoder = jpeg::Decoder::new(data);
+    if ycbcr {
+        decoder.set_color_transform(jpeg::ColorTransform::None);
+    }
+    let data = decoder
+        .decode()
+        .map_err(|error| Error::Decode(error.to_string()))?;
+    let info = decoder
+        .info()
+        .ok_or_else(|| Error::Decode("JPEG has no frame header".to_owned()))?;
+    to_rgb(data, info.pixel_format, ycbcr)
+}
+
+/// Converts whatever pixel format a JPEG decoded to into RGB, or YCbCr with `ycbcr`.
+pub fn to_rgb(
+    data: Vec<u8>,
+    pixel_format: jpeg::PixelFormat,
+    ycbcr: bool,
+) -> Result<Vec<u8>, Error> {
+    Ok(match pixel_format {
+        jpeg::PixelFormat::RGB24 => data,
+        jpeg::PixelFormat::L8 if ycbcr => convert::gray_to_ycbcr(&data),
+        jpeg::PixelFormat::L8 => convert::gray_to_rgb(&data),
+        jpeg::PixelFormat::L16 => convert::gray16_to_rgb(&data),
+        jpeg::PixelFormat::CMYK32 if ycbcr => {
+            return Err(Error::Decode(
+                "CMYK JPEGs can't be left in YCbCr, try without --gpu-color-convert".to_owned(),
+            ))
+        }
+        jpeg::PixelFormat::CMYK32 => convert::cmyk_to_rgb(&data),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Gray frames of whatever size it's been switched to.
+    struct Pattern {
+        width: u32,
+        height: u32,
+    }
+
+    impl FrameSource for Pattern {
+        fn next_frame(&mut self) -> anyhow::Result<Vec<u8>> {
+            thread::sleep(Duration::from_millis(1));
+            Ok(vec![128; self.width as usize * self.height as usize * 3])
+        }
+
+        fn set_resolution(&mut self, width: u32, height: u32) -> anyhow::Result<()> {
+            (self.width, self.height) = (width, height);
+            Ok(())
+        }
+    }
+
+    /// One frame, then nothing until `stall`'s sender is dropped, like a driver that stopped
+    /// delivering buffers.
+    struct Stalling {
+        delivered: bool,
+        stall: mpsc::Receiver<()>,
+    }
+
+    impl FrameSource for Stalling {
+        fn next_frame(&mut self) -> anyhow::Result<Vec<u8>> {
+            if std::mem::replace(&mut self.delivered, true) {
+                self.stall.recv().ok();
+                anyhow::bail!("unplugged");
+            }
+            Ok(vec![0; 8 * 6 * 3])
+        }
+    }
+
+    #[test]
+    fn the_watchdog_swaps_in_the_restarted_camera_without_blocking() {
+        let (unplug, stall) = mpsc::channel();
+        let stalling = Stalling {
+            delivered: false,
+            stall,
+        };
+        let mut camera = Camera::from_source(stalling, "stalling", 8, 6);
+        assert!(camera.wait_for_frame(Duration::from_secs(5)));
+        let mut watchdog = Watchdog {
+            name: "stalling".to_owned(),
+            timeout: Duration::from_millis(50),
+            reopen: Box::new(|camera| {
+                camera.restart_with("stalling", |_| {
+                    let pattern = Pattern {
+                        width: 4,
+                        height: 2,
+                    };
+                    Ok(Camera::from_source(pattern, "pattern", 4, 2))
+                })
+            }),
+            restart: None,
+        };
+        thread::sleep(Duration::from_millis(100));
+
+        // the old capture thread is still blocked, the restart waits for it in the background
+        let start = Instant::now();
+        watchdog.check(&mut camera);
+        assert!(watchdog.restart.is_some());
+        thread::sleep(Duration::from_millis(100));
+        watchdog.check(&mut camera);
+        assert!(start.elapsed() < RESTART_TIMEOUT);
+        assert_eq!(camera.size(), (8, 6));
+
+        drop(unplug);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while camera.size() != (4, 2) {
+            assert!(
+                Instant::now() < deadline,
+                "the restarted camera was never swapped in"
+            );
+            thread::sleep(Duration::from_millis(10));
+            watchdog.check(&mut camera);
+        }
+        assert!(watchdog.restart.is_none());
+        assert!(camera.frame().is_complete());
+    }
+
+    #[test]
+    fn frames_match_their_format_across_a_resolution_change() {
+        let source = Pattern {
+            width: 8,
+            height: 6,
+        };
+        let mut camera = Camera::from_source(source, "pattern", 8, 6);
+        assert!(camera.wait_for_frame(Duration::from_secs(5)));
+        camera.set_resolution(4, 2);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let frame = camera.frame();
+            assert!(
+                frame.is_complete(),
+                "{}x{} frame has {} bytes",
+                frame.width,
+                frame.height,
+                frame.data.len()
+            );
+            if (frame.width, frame.height) == (4, 2) {
+                break;
+            }
+            assert!(Instant::now() < deadline, "resolution never changed");
+        }
+    }
+}
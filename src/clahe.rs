@@ -0,0 +1,207 @@
+//! `--clahe`: contrast limited adaptive histogram equalization of a camera's frames, to bring
+//! out detail in shadows and highlights under uneven lighting.
+//!
+//! The equalization is worked out on the CPU from a quarter resolution copy of the luma, as a
+//! gain map that a shader pass then multiplies the frame's texture with. Both cameras use the
+//! same tile grid and clip limit, so the eyes stay alike enough to fuse.
+
+use glium::{uniform, Surface};
+
+use crate::{camera::Frame, shaders, Vertex};
+
+/// The luma is sampled every this many pixels in both directions.
+const DOWNSCALE: usize = 4;
+/// Tiles across and down the frame, each equalized on its own.
+const TILES: usize = 8;
+/// Cap on how much darker pixels are brightened, against amplifying sensor noise.
+const MAX_GAIN: f32 = 4.0;
+
+/// How much each pixel of a quarter resolution grid should be brightened or darkened, at
+/// that grid's size.
+pub struct GainMap {
+    pub gains: Vec<f32>,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Equalizes the histograms of [`TILES`] × [`TILES`] tiles of an RGB or YCbCr frame, after
+/// clipping every bin at `clip_limit` times the mean bin.
+///
+/// Each pixel's gain blends the mappings of the four tiles around it, so there are no seams
+/// between them.
+pub fn gain_map(frame: &Frame, clip_limit: f32) -> GainMap {
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let (small_width, small_height) = (width.div_ceil(DOWNSCALE), height.div_ceil(DOWNSCALE));
+    let luma: Vec<u8> = (0..small_height)
+        .flat_map(|y| (0..small_width).map(move |x| frame.luma(x * DOWNSCALE, y * DOWNSCALE)))
+        .collect();
+
+    let tile_width = small_width.div_ceil(TILES);
+    let tile_height = small_height.div_ceil(TILES);
+    let mut mappings = vec![[0.0f32; 256]; TILES * TILES];
+    for (tile, mapping) in mappings.iter_mut().enumerate() {
+        let (left, top) = ((tile % TILES) * tile_width, (tile / TILES) * tile_height);
+        let mut histogram = [0u32; 256];
+        for y in top..(top + tile_height).min(small_height) {
+            for x in left..(left + tile_width).min(small_width) {
+                histogram[luma[y * small_width + x] as usize] += 1;
+            }
+        }
+        *mapping = equalize(histogram, clip_limit);
+    }
+
+    // tile centers in grid pixels, for interpolating between neighboring tiles
+    let position = |pixel: usize, tile_size: usize| {
+        let tile = (pixel as f32 + 0.5) / tile_size as f32 - 0.5;
+        let first = tile.floor().clamp(0.0, TILES as f32 - 1.0) as usize;
+        let second = (first + 1).min(TILES - 1);
+        (first, second, (tile - first as f32).clamp(0.0, 1.0))
+    };
+    let mut gains = Vec::with_capacity(luma.len());
+    for y in 0..small_height {
+        let (top, bottom, down) = position(y, tile_height);
+        for x in 0..small_width {
+            let (left, right, across) = position(x, tile_width);
+            let value = luma[y * small_width + x] as usize;
+            let at = |column: usize, row: usize| mappings[row * TILES + column][value];
+            let upper = at(left, top) * (1.0 - across) + at(right, top) * across;
+            let lower = at(left, bottom) * (1.0 - across) + at(right, bottom) * across;
+            let equalized = upper * (1.0 - down) + lower * down;
+            gains.push((equalized / (value as f32).max(1.0)).min(MAX_GAIN));
+        }
+    }
+    GainMap {
+        gains,
+        width: small_width as u32,
+        height: small_height as u32,
+    }
+}
+
+/// Maps luma values to 0 to 255 by the cumulative histogram, with the counts above
+/// `clip_limit` times the mean spread evenly over all bins.
+fn equalize(mut histogram: [u32; 256], clip_limit: f32) -> [f32; 256] {
+    let total: u32 = histogram.iter().sum();
+    let mut mapping = [0.0; 256];
+    if total == 0 {
+        return mapping;
+    }
+    let limit = ((clip_limit * total as f32 / 256.0) as u32).max(1);
+    let mut excess = 0;
+    for count in &mut histogram {
+        excess += count.saturating_sub(limit);
+        *count = (*count).min(limit);
+    }
+    let spread = excess as f32 / 256.0;
+    let mut cumulative = 0.0;
+    for (value, count) in histogram.into_iter().enumerate() {
+        cumulative += count as f32 + spread;
+        mapping[value] = cumulative / total as f32 * 255.0;
+    }
+    mapping
+}
+
+/// Builds the pass multiplying a frame's texture by its gain map.
+pub fn program(facade: &impl glium::backend::Facade) -> anyhow::Result<glium::Program> {
+    shaders::compile(
+        facade,
+        "clahe",
+        shaders::QUAD_VERTEX,
+        "
+            #version 140
+            uniform sampler2D tex;
+            uniform sampler2D gain_map;
+            uniform bool ycbcr;
+            in vec2 v_tex_coords;
+            out vec4 f_color;
+
+            void main() {
+                f_color = texture(tex, v_tex_coords);
+                float gain = texture(gain_map, v_tex_coords).r;
+                if (ycbcr) {
+                    // scaling luma and chroma alike is scaling RGB
+                    f_color.rgb = (f_color.rgb - vec3(0.0, 0.5, 0.5)) * gain
+                        + vec3(0.0, 0.5, 0.5);
+                } else {
+                    f_color.rgb *= gain;
+                }
+            }
+        ",
+    )
+}
+
+/// Draws `texture` multiplied by `gain_map` into a new texture of the same size.
+pub fn apply(
+    facade: &impl glium::backend::Facade,
+    program: &glium::Program,
+    (vertex_buffer, index_buffer): (&glium::VertexBuffer<Vertex>, &glium::IndexBuffer<u16>),
+    texture: &glium::texture::Texture2d,
+    gain_map: GainMap,
+    ycbcr: bool,
+) -> anyhow::Result<glium::texture::Texture2d> {
+    let gains = glium::texture::Texture2d::new(
+        facade,
+        glium::texture::RawImage2d {
+            // textures are bottom up
+            data: gain_map
+                .gains
+                .chunks_exact(gain_map.width as usize)
+                .rev()
+                .flatten()
+                .copied()
+                .collect(),
+            width: gain_map.width,
+            height: gain_map.height,
+            format: glium::texture::ClientFormat::F32,
+        },
+    )?;
+    let (width, height) = texture.dimensions();
+    let equalized = glium::texture::Texture2d::empty(facade, width, height)?;
+    glium::framebuffer::SimpleFrameBuffer::new(facade, &equalized)?.draw(
+        vertex_buffer,
+        index_buffer,
+        program,
+        &uniform! {
+            tex: texture,
+            gain_map: gains.sampled().magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear),
+            ycbcr: ycbcr,
+        },
+        &Default::default(),
+    )?;
+    Ok(equalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::PixelFormat;
+
+    #[test]
+    fn brightens_dark_low_contrast_frames() {
+        let (width, height) = (64, 64);
+        let frame = Frame {
+            data: (0..width * height)
+                .flat_map(|pixel| [20 + (pixel % width) as u8 / 4; 3])
+                .collect(),
+            width,
+            height,
+            pixel_format: PixelFormat::Rgb,
+            timestamp: std::time::Instant::now(),
+        };
+        let map = gain_map(&frame, 2.0);
+        assert_eq!((map.width, map.height), (16, 16));
+        let mean = map.gains.iter().sum::<f32>() / map.gains.len() as f32;
+        assert!(mean > 1.5, "mean gain {mean}");
+        assert!(map.gains.iter().all(|&gain| gain <= MAX_GAIN));
+    }
+
+    #[test]
+    fn clipping_limits_the_contrast_gained() {
+        let mut histogram = [0; 256];
+        histogram[100] = 1000;
+        histogram[101] = 1000;
+        let unclipped = equalize(histogram, 256.0);
+        let clipped = equalize(histogram, 2.0);
+        let step = |mapping: [f32; 256]| mapping[101] - mapping[100];
+        assert!(step(clipped) < step(unclipped));
+    }
+}
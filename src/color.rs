@@ -0,0 +1,65 @@
+//! CPU-side color analysis of decoded frames, feeding the per-camera shader uniforms.
+
+/// Mean red, green and blue of an interleaved RGB frame, sampling every `step`th pixel.
+pub fn channel_means(data: &[u8], step: usize) -> [f32; 3] {
+    let mut sums = [0u64; 3];
+    let mut count = 0u64;
+    for pixel in data.chunks_exact(3).step_by(step.max(1)) {
+        for (sum, &value) in sums.iter_mut().zip(pixel) {
+            *sum += u64::from(value);
+        }
+        count += 1;
+    }
+    sums.map(|sum| sum as f32 / count.max(1) as f32)
+}
+
+/// Mean red, green and blue of raw Bayer data whose red sample is at `red` in each 2x2 block,
+/// sampling every `step`th block.
+pub fn bayer_means(data: &[u8], width: u32, red: (u32, u32), step: usize) -> [f32; 3] {
+    let width = width as usize;
+    let (mut sums, mut counts) = ([0u64; 3], [0u64; 3]);
+    if width >= 2 {
+        let blocks =
+            (0..data.len() / width / 2).flat_map(|y| (0..width / 2).map(move |x| (x * 2, y * 2)));
+        for (x, y) in blocks.step_by(step.max(1)) {
+            for (x, y) in [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)] {
+                let channel = match ((x + red.0 as usize) % 2, (y + red.1 as usize) % 2) {
+                    (0, 0) => 0,
+                    (1, 1) => 2,
+                    _ => 1,
+                };
+                sums[channel] += u64::from(data[y * width + x]);
+                counts[channel] += 1;
+            }
+        }
+    }
+    [0, 1, 2].map(|channel| sums[channel] as f32 / counts[channel].max(1) as f32)
+}
+
+/// Rec. 601 luma of an 8-bit RGB color like [`luminance`], in integer arithmetic for
+/// per-pixel loops.
+pub fn luma([r, g, b]: [u8; 3]) -> u8 {
+    ((77 * u32::from(r) + 150 * u32::from(g) + 29 * u32::from(b)) >> 8) as u8
+}
+
+/// Rec. 601 luma of an RGB color.
+pub fn luminance([r, g, b]: [f32; 3]) -> f32 {
+    0.299 * r + 0.587 * g + 0.114 * b
+}
+
+/// Converts full-range JFIF YCbCr, as left in the buffer by `--gpu-color-convert`, to RGB.
+pub fn ycbcr_to_rgb([y, cb, cr]: [f32; 3]) -> [f32; 3] {
+    let (cb, cr) = (cb - 128.0, cr - 128.0);
+    [
+        y + 1.402 * cr,
+        y - 0.344136 * cb - 0.714136 * cr,
+        y + 1.772 * cb,
+    ]
+}
+
+/// Gray-world white balance: gains that turn each camera's average color into the same gray,
+/// the average over all cameras, so both eyes end up matching.
+pub fn gray_world_gains<const N: usize>(means: [[f32; 3]; N]) -> [[f32; 3]; N] {
+    let gray = means.iter().flatten().sum::<f32>() / (3 * N) as f32;
+    means.map(|mean| mean.map(|channel| if channel > 0.0 { gray / channel } else { 1.0 }))
+}
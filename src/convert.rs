@@ -0,0 +1,190 @@
+//! Conversions of decoded pixel data to the interleaved 8-bit RGB all frame buffers hold.
+
+/// Repeats each 8-bit luminance sample for red, green and blue.
+pub fn gray_to_rgb(gray: &[u8]) -> Vec<u8> {
+    gray.iter().flat_map(|&l| [l, l, l]).collect()
+}
+
+/// Like [`gray_to_rgb`] for 16-bit samples in native byte order, keeping the upper 8 bits.
+pub fn gray16_to_rgb(gray: &[u8]) -> Vec<u8> {
+    gray.chunks_exact(2)
+        .map(|l| (u16::from_ne_bytes([l[0], l[1]]) >> 8) as u8)
+        .flat_map(|l| [l, l, l])
+        .collect()
+}
+
+/// Expands 8-bit luminance to full-range YCbCr without any color.
+pub fn gray_to_ycbcr(gray: &[u8]) -> Vec<u8> {
+    gray.iter().flat_map(|&l| [l, 128, 128]).collect()
+}
+
+/// Converts CMYK, where 0 means no ink, by multiplying each color with the missing black.
+pub fn cmyk_to_rgb(cmyk: &[u8]) -> Vec<u8> {
+    cmyk.chunks_exact(4)
+        .flat_map(|pixel| {
+            let white = 255 - u16::from(pixel[3]);
+            let channel = |ink: u8| ((255 - u16::from(ink)) * white / 255) as u8;
+            [channel(pixel[0]), channel(pixel[1]), channel(pixel[2])]
+        })
+        .collect()
+}
+
+/// Swaps the red and blue channels of 8-bit BGR.
+pub fn bgr_to_rgb(bgr: &[u8]) -> Vec<u8> {
+    bgr.chunks_exact(3)
+        .flat_map(|pixel| [pixel[2], pixel[1], pixel[0]])
+        .collect()
+}
+
+/// Converts packed 4:2:2 YUV, where each group of `Y0 U Y1 V` holds two pixels.
+pub fn yuyv_to_rgb(yuyv: &[u8]) -> Vec<u8> {
+    yuyv.chunks_exact(4)
+        .flat_map(|group| {
+            let [y0, u, y1, v] = [group[0], group[1], group[2], group[3]];
+            let [r0, g0, b0] = yuv_to_rgb(y0, u, v);
+            let [r1, g1, b1] = yuv_to_rgb(y1, u, v);
+            [r0, g0, b0, r1, g1, b1]
+        })
+        .collect()
+}
+
+/// Converts 4:2:0 YUV with a full-resolution Y plane followed by a half-resolution plane of
+/// interleaved U and V, without padding at the end of rows.
+pub fn nv12_to_rgb(nv12: &[u8], width: u32, height: u32) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let (luma, chroma) = nv12.split_at((width * height).min(nv12.len()));
+    // the chroma rows are padded to an even width like the luma rows
+    let chroma_width = width.div_ceil(2) * 2;
+    let mut rgb = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let uv = y / 2 * chroma_width + x / 2 * 2;
+            let (Some(&l), Some(&[u, v])) = (luma.get(y * width + x), chroma.get(uv..uv + 2))
+            else {
+                return rgb;
+            };
+            rgb.extend(yuv_to_rgb(l, u, v));
+        }
+    }
+    rgb
+}
+
+/// BT.601 limited-range YUV, the way webcams deliver it, to full-range RGB.
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> [u8; 3] {
+    let c = 298 * (i32::from(y) - 16);
+    let d = i32::from(u) - 128;
+    let e = i32::from(v) - 128;
+    let channel = |value: i32| ((value + 128) >> 8).clamp(0, 255) as u8;
+    [
+        channel(c + 409 * e),
+        channel(c - 100 * d - 208 * e),
+        channel(c + 516 * d),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gray() {
+        assert_eq!(
+            gray_to_rgb(&[0, 128, 255]),
+            [0, 0, 0, 128, 128, 128, 255, 255, 255]
+        );
+    }
+
+    #[test]
+    fn gray16() {
+        let gray: Vec<u8> = [0x0000u16, 0x80ff, 0xffff]
+            .iter()
+            .flat_map(|l| l.to_ne_bytes())
+            .collect();
+        assert_eq!(
+            gray16_to_rgb(&gray),
+            [0, 0, 0, 0x80, 0x80, 0x80, 255, 255, 255]
+        );
+    }
+
+    #[test]
+    fn gray_ycbcr() {
+        assert_eq!(gray_to_ycbcr(&[0, 200]), [0, 128, 128, 200, 128, 128]);
+    }
+
+    #[test]
+    fn cmyk() {
+        let cmyk = [
+            0, 0, 0, 0, // white
+            0, 0, 0, 255, // black
+            255, 0, 0, 0, // cyan
+            0, 255, 255, 51, // red at 80%
+        ];
+        assert_eq!(
+            cmyk_to_rgb(&cmyk),
+            [255, 255, 255, 0, 0, 0, 0, 255, 255, 204, 0, 0]
+        );
+    }
+
+    #[test]
+    fn bgr() {
+        assert_eq!(bgr_to_rgb(&[1, 2, 3, 255, 0, 0]), [3, 2, 1, 0, 0, 255]);
+    }
+
+    #[test]
+    fn yuv() {
+        // limited range: 16 is black and 235 white
+        assert_eq!(yuv_to_rgb(16, 128, 128), [0, 0, 0]);
+        assert_eq!(yuv_to_rgb(235, 128, 128), [255, 255, 255]);
+        assert_eq!(yuv_to_rgb(126, 128, 128), [128, 128, 128]);
+        assert_eq!(yuv_to_rgb(81, 90, 240), [255, 0, 0]);
+        // out of gamut values are clamped
+        assert_eq!(yuv_to_rgb(255, 255, 255), [255, 125, 255]);
+        assert_eq!(yuv_to_rgb(0, 0, 0), [0, 135, 0]);
+    }
+
+    #[test]
+    fn yuyv() {
+        assert_eq!(
+            yuyv_to_rgb(&[16, 128, 235, 128, 81, 90, 81, 240]),
+            [0, 0, 0, 255, 255, 255, 255, 0, 0, 255, 0, 0]
+        );
+    }
+
+    #[test]
+    fn nv12() {
+        // a 4x2 image whose left half is gray and right half red
+        let nv12 = [
+            16, 235, 81, 81, // first row of Y
+            235, 16, 81, 81, // second row of Y
+            128, 128, 90, 240, // U and V of both 2x2 blocks
+        ];
+        assert_eq!(
+            nv12_to_rgb(&nv12, 4, 2),
+            [
+                0, 0, 0, 255, 255, 255, 255, 0, 0, 255, 0, 0, //
+                255, 255, 255, 0, 0, 0, 255, 0, 0, 255, 0, 0,
+            ]
+        );
+    }
+
+    #[test]
+    fn nv12_odd_size() {
+        // a 3x3 image has 2x2 chroma samples
+        let mut nv12 = vec![126; 9];
+        nv12[2] = 81;
+        nv12.extend([128, 128, 90, 240, 128, 128, 128, 128]);
+        let rgb = nv12_to_rgb(&nv12, 3, 3);
+        assert_eq!(rgb.len(), 27);
+        assert_eq!(rgb[6..9], [255, 0, 0]);
+        assert_eq!(rgb[24..27], [128, 128, 128]);
+    }
+
+    #[test]
+    fn partial_pixels_are_dropped() {
+        assert_eq!(gray16_to_rgb(&[0xff, 0xff, 0xff]).len(), 3);
+        assert_eq!(cmyk_to_rgb(&[0; 7]).len(), 3);
+        assert_eq!(bgr_to_rgb(&[0; 5]).len(), 3);
+        assert_eq!(yuyv_to_rgb(&[0; 7]).len(), 6);
+        assert_eq!(nv12_to_rgb(&[0; 5], 2, 2).len(), 0);
+    }
+}
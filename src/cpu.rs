@@ -0,0 +1,143 @@
+//! Software fallback that composites the anaglyph on the CPU and presents it through
+//! `softbuffer`, for machines where the GLSL programs can't be compiled or run fast enough.
+
+use std::{num::NonZeroU32, rc::Rc, time::Instant};
+
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::EventLoop,
+    window::WindowBuilder,
+};
+
+use crate::{
+    camera::{self, ImageBuffer, Trigger},
+    metrics,
+    scheme::ChannelMask,
+};
+
+/// Per-camera sampling options, mirroring what the fragment shaders do on the GPU.
+#[derive(Debug, Clone, Copy)]
+pub struct EyeOptions {
+    pub flip_x: bool,
+    pub flip_y: bool,
+}
+
+/// A decoded RGB frame borrowed from a capture buffer.
+pub struct Eye<'a> {
+    pub data: &'a [u8],
+    pub width: u32,
+    pub height: u32,
+    pub options: EyeOptions,
+    pub mask: ChannelMask,
+}
+
+impl Eye<'_> {
+    /// Samples the nearest texel for normalized, top-down coordinates `(u, v)`.
+    fn sample(&self, mut u: f32, mut v: f32) -> [u8; 3] {
+        // Same transforms as the shader: `flip_y` turns the image upside down (which also
+        // mirrors it horizontally), `flip_x` mirrors it horizontally.
+        if self.options.flip_y {
+            u = 1.0 - u;
+            v = 1.0 - v;
+        }
+        if self.options.flip_x {
+            u = 1.0 - u;
+        }
+
+        let x = ((u * self.width as f32) as u32).min(self.width - 1);
+        let y = ((v * self.height as f32) as u32).min(self.height - 1);
+        let i = ((y * self.width + x) * 3) as usize;
+        match self.data.get(i..i + 3) {
+            Some(px) => [px[0], px[1], px[2]],
+            None => [0, 0, 0],
+        }
+    }
+}
+
+/// Composites both eyes into `out`, one `0RGB` pixel per `u32` as expected by `softbuffer`.
+pub fn composite(eyes: &[Eye], out: &mut [u32], width: u32, height: u32) {
+    for y in 0..height {
+        let v = (y as f32 + 0.5) / height as f32;
+        for x in 0..width {
+            let u = (x as f32 + 0.5) / width as f32;
+            let mut rgb = [0u8; 3];
+            for eye in eyes {
+                let px = eye.sample(u, v);
+                if eye.mask.red {
+                    rgb[0] = px[0];
+                }
+                if eye.mask.green {
+                    rgb[1] = px[1];
+                }
+                if eye.mask.blue {
+                    rgb[2] = px[2];
+                }
+            }
+            out[(y * width + x) as usize] =
+                (rgb[0] as u32) << 16 | (rgb[1] as u32) << 8 | rgb[2] as u32;
+        }
+    }
+}
+
+/// Shows the anaglyph in a window until it's closed, firing `triggers` for every frame and
+/// recording how long compositing each frame takes in `render_ms`.
+pub fn run(
+    title: &str,
+    mut cameras: [(ImageBuffer, EyeOptions, ChannelMask); 2],
+    triggers: &[Trigger],
+    render_ms: &mut metrics::Reservoir,
+) -> anyhow::Result<()> {
+    let event_loop = EventLoop::new()?;
+    let window = Rc::new(WindowBuilder::new().with_title(title).build(&event_loop)?);
+    window.set_fullscreen(Some(winit::window::Fullscreen::Borderless(None)));
+    window.focus_window();
+    event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
+
+    // softbuffer's errors aren't `Send`, so they're flattened into strings for anyhow
+    let context = softbuffer::Context::new(window.clone())
+        .map_err(|e| anyhow::anyhow!("Couldn't connect to display: {e}"))?;
+    let mut surface = softbuffer::Surface::new(&context, window.clone())
+        .map_err(|e| anyhow::anyhow!("Couldn't create software framebuffer: {e}"))?;
+
+    event_loop.run(move |event, elwt| match event {
+        Event::WindowEvent {
+            event: WindowEvent::CloseRequested,
+            ..
+        } => elwt.exit(),
+        Event::AboutToWait => window.request_redraw(),
+        Event::WindowEvent {
+            event: WindowEvent::RedrawRequested,
+            ..
+        } => {
+            let size = window.inner_size();
+            let (Some(width), Some(height)) =
+                (NonZeroU32::new(size.width), NonZeroU32::new(size.height))
+            else {
+                return;
+            };
+            surface.resize(width, height).unwrap();
+            camera::fire(triggers);
+
+            let eyes: Vec<_> = cameras
+                .iter_mut()
+                .map(|(buffer, options, mask)| (buffer.read(), options, mask))
+                .filter(|(frame, _, _)| frame.is_complete())
+                .map(|(frame, options, mask)| Eye {
+                    data: &frame.data,
+                    width: frame.width,
+                    height: frame.height,
+                    options: *options,
+                    mask: *mask,
+                })
+                .collect();
+
+            let mut buffer = surface.buffer_mut().unwrap();
+            let start = Instant::now();
+            composite(&eyes, &mut buffer, width.get(), height.get());
+            render_ms.record(start.elapsed().as_secs_f32() * 1000.0);
+            buffer.present().unwrap();
+        }
+        _ => (),
+    })?;
+    Ok(())
+}
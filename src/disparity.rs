@@ -0,0 +1,192 @@
+//! A rough horizontal disparity map from block matching downscaled frames, meant for checking
+//! a rig's baseline and eye order rather than for measuring depth, also done automatically
+//! for `--auto-eye-order`.
+
+use crate::{camera::Frame, stereo_image::Image};
+
+/// Frames are downscaled by this factor before matching.
+const SCALE: usize = 4;
+/// Side of the matched blocks in downscaled pixels, and of the map's pixels.
+const BLOCK: usize = 8;
+/// Largest disparity searched in either direction, in downscaled pixels.
+const MAX_DISPARITY: i32 = 24;
+/// Blocks with less luminance variance than this are too flat to match and stay black.
+const MIN_VARIANCE: f32 = 16.0;
+/// How many blocks need a disparity other than 0 for [`swapped`] to decide.
+const MIN_MATCHED: usize = 20;
+/// The share of those that has to agree on a direction.
+const MIN_AGREEMENT: f32 = 0.75;
+
+struct Luma {
+    data: Vec<u8>,
+    width: usize,
+    height: usize,
+}
+
+impl Luma {
+    /// The luma of `frame` downscaled by [`SCALE`], by point sampling.
+    fn of(frame: &Frame) -> Self {
+        let (width, height) = (frame.width as usize / SCALE, frame.height as usize / SCALE);
+        let data = (0..height)
+            .flat_map(|y| (0..width).map(move |x| frame.luma(x * SCALE, y * SCALE)))
+            .collect();
+        Luma {
+            data,
+            width,
+            height,
+        }
+    }
+
+    fn at(&self, x: i32, y: i32) -> Option<i32> {
+        let inside = (0..self.width as i32).contains(&x) && (0..self.height as i32).contains(&y);
+        inside.then(|| i32::from(self.data[y as usize * self.width + x as usize]))
+    }
+}
+
+/// Matches blocks of `left` along the rows of `right`, moved down by `right_shift_y` texels
+/// like the renderer's camera2 offset, and colors them from blue (right image further right,
+/// e.g. swapped eyes) through green (no disparity) to red (further left, i.e. nearer).
+pub fn heatmap(left: &Frame, right: &Frame, right_shift_y: i32) -> Image {
+    let (width, height, disparities) = disparities(left, right, right_shift_y);
+    let mut data = Vec::with_capacity(width * height * 3);
+    for disparity in disparities {
+        match disparity {
+            Some(disparity) => {
+                let t = (disparity + MAX_DISPARITY) as f32 / (2 * MAX_DISPARITY) as f32;
+                data.extend(jet(t));
+            }
+            None => data.extend([0, 0, 0]),
+        }
+    }
+    Image {
+        data,
+        width: width as u32,
+        height: height as u32,
+    }
+}
+
+/// Whether the cameras seem to be the wrong way round, because most blocks of the right image
+/// are further right than in the left one like in [`heatmap`]'s blue, or `None` if too few
+/// blocks clearly agree either way.
+pub fn swapped(left: &Frame, right: &Frame, right_shift_y: i32) -> Option<bool> {
+    let (_, _, disparities) = disparities(left, right, right_shift_y);
+    let (nearer, further) =
+        disparities
+            .into_iter()
+            .flatten()
+            .fold((0, 0), |(nearer, further), disparity| match disparity {
+                1.. => (nearer + 1, further),
+                ..=-1 => (nearer, further + 1),
+                0 => (nearer, further),
+            });
+    let matched = nearer + further;
+    if matched < MIN_MATCHED {
+        return None;
+    }
+    let agreement = |count: usize| count as f32 / matched as f32 >= MIN_AGREEMENT;
+    match (agreement(nearer), agreement(further)) {
+        (true, _) => Some(false),
+        (_, true) => Some(true),
+        _ => None,
+    }
+}
+
+/// The best matching disparity of each block by rows, `None` for blocks too flat to match,
+/// with the size of that grid.
+fn disparities(
+    left: &Frame,
+    right: &Frame,
+    right_shift_y: i32,
+) -> (usize, usize, Vec<Option<i32>>) {
+    let (left, right) = (Luma::of(left), Luma::of(right));
+    let shift_y = right_shift_y / SCALE as i32;
+    let (width, height) = (left.width / BLOCK, left.height / BLOCK);
+    let mut disparities = Vec::with_capacity(width * height);
+    for block_y in 0..height {
+        for block_x in 0..width {
+            let (x0, y0) = ((block_x * BLOCK) as i32, (block_y * BLOCK) as i32);
+            let block = || {
+                (0..BLOCK as i32)
+                    .flat_map(move |y| (0..BLOCK as i32).map(move |x| (x0 + x, y0 + y)))
+            };
+
+            let (sum, sum_sq) = block()
+                .filter_map(|(x, y)| left.at(x, y))
+                .fold((0.0, 0.0), |(s, sq), l| (s + l as f32, sq + (l * l) as f32));
+            let n = (BLOCK * BLOCK) as f32;
+            if sum_sq / n - (sum / n).powi(2) < MIN_VARIANCE {
+                disparities.push(None);
+                continue;
+            }
+
+            let cost = |disparity: i32| -> Option<i32> {
+                block()
+                    .map(|(x, y)| {
+                        Some((left.at(x, y)? - right.at(x - disparity, y - shift_y)?).abs())
+                    })
+                    .sum()
+            };
+            let best = (-MAX_DISPARITY..=MAX_DISPARITY)
+                .filter_map(|disparity| Some((cost(disparity)?, disparity)))
+                .min();
+            disparities.push(best.map(|(_, disparity)| disparity));
+        }
+    }
+    (width, height, disparities)
+}
+
+/// The classic blue-cyan-green-yellow-red color map, `t` from 0 to 1.
+fn jet(t: f32) -> [u8; 3] {
+    let channel = |center: f32| ((1.5 - (4.0 * t - center).abs()).clamp(0.0, 1.0) * 255.0) as u8;
+    [channel(3.0), channel(2.0), channel(1.0)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::{Bayer, PixelFormat};
+
+    /// Noise at 4x4 pixel granularity so it survives the downscaling, moved left by `shift`.
+    fn noise(shift: u32) -> Vec<u8> {
+        let (width, height) = (256, 128);
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height as u32 {
+            for x in 0..width as u32 {
+                let (cell_x, cell_y) = ((x + shift) / 4, y / 4);
+                let hash = (cell_x.wrapping_mul(73_856_093) ^ cell_y.wrapping_mul(19_349_663))
+                    .wrapping_mul(2_654_435_761);
+                data.push((hash >> 24) as u8);
+            }
+        }
+        data
+    }
+
+    fn frame(data: &[u8]) -> Frame {
+        Frame {
+            data: data.to_vec(),
+            width: 256,
+            height: 128,
+            pixel_format: PixelFormat::Bayer(Bayer::Rggb),
+            timestamp: std::time::Instant::now(),
+        }
+    }
+
+    #[test]
+    fn tells_the_eye_order() {
+        let (near_left, near_right) = (noise(0), noise(16));
+        assert_eq!(
+            swapped(&frame(&near_left), &frame(&near_right), 0),
+            Some(false)
+        );
+        assert_eq!(
+            swapped(&frame(&near_right), &frame(&near_left), 0),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn undecided_without_disparity() {
+        let image = noise(0);
+        assert_eq!(swapped(&frame(&image), &frame(&image), 0), None);
+    }
+}
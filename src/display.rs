@@ -0,0 +1,235 @@
+//! OpenGL context and window creation, like glium's `SimpleWindowBuilder` but with control
+//! over the framebuffer configuration.
+
+use glium::backend::glutin::{glutin, Display};
+use glutin::{
+    config::ConfigTemplateBuilder,
+    context::{ContextApi, ContextAttributesBuilder, Version},
+    display::{DisplayApiPreference, GetGlDisplay},
+    prelude::*,
+    surface::{SurfaceAttributesBuilder, WindowSurface},
+};
+use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
+use std::{ffi::CStr, num::NonZeroU32};
+use winit::{
+    event_loop::EventLoop, platform::x11::register_xlib_error_hook, window::Window,
+    window::WindowBuilder,
+};
+
+use crate::error::Error;
+
+/// An OpenGL context profile to ask the driver for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GlProfile {
+    /// Only the functionality that isn't deprecated, needs OpenGL 3.2 or later
+    Core,
+    /// Everything including the deprecated fixed-function pipeline
+    Compat,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DisplayOptions {
+    /// Request a framebuffer with an alpha channel the compositor blends with the desktop.
+    pub transparent: bool,
+    /// Print all driver debug output and load `glGetError` for [`GlErrors::check`].
+    pub gl_debug: bool,
+    /// Context profile to request instead of the driver's default.
+    pub gl_profile: Option<GlProfile>,
+    /// Major and minor OpenGL version to request instead of the driver's default.
+    pub gl_version: Option<(u8, u8)>,
+    /// Prefer a framebuffer that sRGB-encodes what's drawn into it, see
+    /// `glium::Capabilities::srgb` for whether there is one.
+    pub srgb: bool,
+}
+
+/// Direct access to `glGetError`, which glium otherwise only exposes as a panic.
+pub struct GlErrors(extern "system" fn() -> u32);
+
+impl GlErrors {
+    /// Logs every error the driver has recorded since the last check.
+    pub fn check(&self, stage: &str) {
+        loop {
+            let error = match (self.0)() {
+                0 => return,
+                0x0500 => "GL_INVALID_ENUM",
+                0x0501 => "GL_INVALID_VALUE",
+                0x0502 => "GL_INVALID_OPERATION",
+                0x0503 => "GL_STACK_OVERFLOW",
+                0x0504 => "GL_STACK_UNDERFLOW",
+                0x0505 => "GL_OUT_OF_MEMORY",
+                0x0506 => "GL_INVALID_FRAMEBUFFER_OPERATION",
+                _ => "unknown GL error",
+            };
+            eprintln!("\n{error} on {stage}");
+        }
+    }
+}
+
+pub fn build(
+    event_loop: &EventLoop<()>,
+    title: &str,
+    options: DisplayOptions,
+) -> Result<(Window, Display<WindowSurface>, Option<GlErrors>), Error> {
+    let window_builder = WindowBuilder::new()
+        .with_title(title)
+        .with_transparent(options.transparent);
+    let template = ConfigTemplateBuilder::new()
+        .with_transparency(options.transparent)
+        .build();
+    // The display and config are created the way `glutin_winit::DisplayBuilder` does, but
+    // without its picker callback, which has to return a config even when there are none.
+    let preference = DisplayApiPreference::GlxThenEgl(Box::new(register_xlib_error_hook));
+    let gl_display =
+        unsafe { glutin::display::Display::new(event_loop.raw_display_handle(), preference) }
+            .map_err(gl_init)?;
+    let configs: Vec<_> = unsafe { gl_display.find_configs(template) }
+        .map_err(gl_init)?
+        .collect();
+    let transparent = |config: &&glutin::config::Config| {
+        !options.transparent || config.supports_transparency() == Some(true)
+    };
+    let preferred = configs
+        .iter()
+        .filter(transparent)
+        .find(|config| !options.srgb || config.srgb_capable())
+        .or_else(|| configs.iter().find(transparent));
+    let gl_config = match preferred {
+        Some(config) => config.clone(),
+        None => {
+            let config = configs
+                .first()
+                .ok_or_else(|| gl_init("No framebuffer config found"))?;
+            eprintln!("WARNING: no transparent framebuffer available");
+            config.clone()
+        }
+    };
+    let window = glutin_winit::finalize_window(event_loop, window_builder, &gl_config)
+        .map_err(|e| Error::GlInit(format!("Couldn't create window: {e}")))?;
+
+    let (width, height): (u32, u32) = window.inner_size().into();
+    let attrs = SurfaceAttributesBuilder::<WindowSurface>::new()
+        .with_srgb(options.srgb.then_some(true))
+        .build(
+            window.raw_window_handle(),
+            NonZeroU32::new(width).unwrap_or(NonZeroU32::MIN),
+            NonZeroU32::new(height).unwrap_or(NonZeroU32::MIN),
+        );
+    let surface = unsafe {
+        gl_config
+            .display()
+            .create_window_surface(&gl_config, &attrs)
+            .map_err(gl_init)?
+    };
+    let mut requested = ContextAttributesBuilder::new();
+    if let Some(profile) = options.gl_profile {
+        requested = requested.with_profile(match profile {
+            GlProfile::Core => glutin::context::GlProfile::Core,
+            GlProfile::Compat => glutin::context::GlProfile::Compatibility,
+        });
+    }
+    if let Some((major, minor)) = options.gl_version {
+        requested =
+            requested.with_context_api(ContextApi::OpenGl(Some(Version::new(major, minor))));
+    }
+    let requested = requested.build(Some(window.raw_window_handle()));
+    let context = unsafe { gl_config.display().create_context(&gl_config, &requested) };
+    let context = match context {
+        Err(error) if options.gl_profile.is_some() || options.gl_version.is_some() => {
+            eprintln!(
+                "WARNING: couldn't create the requested OpenGL context ({error}), \
+                 using the driver's default"
+            );
+            let default = ContextAttributesBuilder::new().build(Some(window.raw_window_handle()));
+            unsafe { gl_config.display().create_context(&gl_config, &default) }
+        }
+        context => context,
+    }
+    .and_then(|context| context.make_current(&surface))
+    .map_err(gl_init)?;
+
+    let gl_errors = options
+        .gl_debug
+        .then(|| {
+            let name = CStr::from_bytes_with_nul(b"glGetError\0").unwrap();
+            let address = gl_config.display().get_proc_address(name);
+            if address.is_null() {
+                return Err(gl_init("The driver doesn't provide glGetError"));
+            }
+            // SAFETY: glGetError has had this signature in every GL version
+            Ok(GlErrors(unsafe {
+                std::mem::transmute::<*const std::ffi::c_void, extern "system" fn() -> u32>(address)
+            }))
+        })
+        .transpose()?;
+    let debug = if options.gl_debug {
+        glium::debug::DebugCallbackBehavior::PrintAll
+    } else {
+        Default::default()
+    };
+    let display = Display::with_debug(context, surface, debug).map_err(gl_init)?;
+
+    Ok((window, display, gl_errors))
+}
+
+fn gl_init(error: impl std::fmt::Display) -> Error {
+    Error::GlInit(error.to_string())
+}
+
+/// A surfaceless EGL context to render offscreen, for `--once` and tests, or `None` without an
+/// EGL device.
+pub fn headless() -> Option<std::rc::Rc<glium::backend::Context>> {
+    use glutin::{
+        api::egl::{context::PossiblyCurrentContext, device::Device, display::Display},
+        config::ConfigSurfaceTypes,
+    };
+
+    struct Headless {
+        display: Display,
+        context: PossiblyCurrentContext,
+    }
+
+    // SAFETY: the context is only made current on the thread that created it
+    unsafe impl glium::backend::Backend for Headless {
+        fn swap_buffers(&self) -> Result<(), glium::SwapBuffersError> {
+            Ok(())
+        }
+
+        unsafe fn get_proc_address(&self, symbol: &str) -> *const std::ffi::c_void {
+            let symbol = std::ffi::CString::new(symbol).unwrap();
+            self.display.get_proc_address(&symbol)
+        }
+
+        /// There's no default framebuffer, everything is drawn into textures.
+        fn get_framebuffer_dimensions(&self) -> (u32, u32) {
+            (0, 0)
+        }
+
+        fn resize(&self, _: (u32, u32)) {}
+
+        fn is_current(&self) -> bool {
+            self.context.is_current()
+        }
+
+        unsafe fn make_current(&self) {
+            self.context.make_current_surfaceless().unwrap();
+        }
+    }
+
+    let (display, context) = Device::query_devices().ok()?.find_map(|device| {
+        let display = unsafe { Display::with_device(&device, None) }.ok()?;
+        let template = ConfigTemplateBuilder::new()
+            .with_surface_type(ConfigSurfaceTypes::empty())
+            .build();
+        let config = unsafe { display.find_configs(template) }.ok()?.next()?;
+        let attributes = ContextAttributesBuilder::new().build(None);
+        let context = unsafe { display.create_context(&config, &attributes) }
+            .ok()?
+            .make_current_surfaceless()
+            .ok()?;
+        Some((display, context))
+    })?;
+    unsafe {
+        glium::backend::Context::new(Headless { display, context }, false, Default::default())
+    }
+    .ok()
+}
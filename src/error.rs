@@ -0,0 +1,24 @@
+//! Errors of setting up the cameras and the display, for callers that want to tell failures
+//! apart rather than just show them.
+
+use std::io;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The device node couldn't be opened, e.g. because it doesn't exist or is busy.
+    #[error("Couldn't open {path}")]
+    DeviceOpen {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+    /// The device doesn't accept a format that can be captured from.
+    #[error("{path}: {message}")]
+    FormatNegotiation { path: String, message: String },
+    /// A frame couldn't be decoded into RGB.
+    #[error("failed to decode JPEG: {0}")]
+    Decode(String),
+    /// The window or OpenGL context couldn't be created.
+    #[error("Couldn't initialize OpenGL: {0}")]
+    GlInit(String),
+}
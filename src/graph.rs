@@ -0,0 +1,184 @@
+//! `--graph`: plots the intervals between the last frames each camera captured and the window
+//! rendered as lines in the bottom left corner, toggled with G, so stutter and dropped frames
+//! stand out.
+
+use glium::{implement_vertex, index::PrimitiveType, uniform, Surface};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::shaders;
+
+/// Frames plotted across the graph.
+const SAMPLES: usize = 300;
+/// The interval at the top of the graph; longer ones are cut off there.
+const MAX_MS: f32 = 100.0;
+/// Size of the graph in pixels, at most a third of the window wide.
+const WIDTH: u32 = 320;
+const HEIGHT: u32 = 120;
+
+/// Colors of camera1's, camera2's and the render loop's lines.
+const COLORS: [[f32; 4]; 3] = [
+    [1.0, 0.3, 0.3, 1.0],
+    [0.3, 1.0, 1.0, 1.0],
+    [1.0, 1.0, 1.0, 1.0],
+];
+
+#[derive(Copy, Clone)]
+struct Point {
+    position: [f32; 2],
+}
+
+implement_vertex!(Point, position);
+
+pub struct Graph {
+    pub visible: bool,
+    /// Milliseconds between frames, oldest first, in the order of [`COLORS`].
+    intervals: [VecDeque<f32>; 3],
+    /// When each camera's newest frame was captured, to tell when there's a new one.
+    captured: [Option<Instant>; 2],
+    program: glium::Program,
+}
+
+impl Graph {
+    pub fn new(facade: &impl glium::backend::Facade, visible: bool) -> anyhow::Result<Self> {
+        let program = shaders::compile(
+            facade,
+            "graph",
+            "
+                #version 140
+                in vec2 position;
+                void main() {
+                    gl_Position = vec4(position, 0.0, 1.0);
+                }
+            ",
+            "
+                #version 140
+                uniform vec4 color;
+                out vec4 f_color;
+                void main() {
+                    f_color = color;
+                }
+            ",
+        )?;
+        Ok(Graph {
+            visible,
+            intervals: Default::default(),
+            captured: [None; 2],
+            program,
+        })
+    }
+
+    /// Records a rendered frame `render` after the previous one, and the cameras' frames if
+    /// they have new ones since, captured at `captured`.
+    pub fn record(&mut self, render: Duration, captured: [Option<Instant>; 2]) {
+        for (camera, captured) in captured.into_iter().enumerate() {
+            let previous = std::mem::replace(&mut self.captured[camera], captured);
+            if let (Some(previous), Some(captured)) = (previous, captured) {
+                if captured > previous {
+                    push(&mut self.intervals[camera], captured - previous);
+                }
+            }
+        }
+        push(&mut self.intervals[2], render);
+    }
+
+    /// Draws the graph into the bottom left corner of `area` if it's shown.
+    pub fn draw(
+        &self,
+        facade: &impl glium::backend::Facade,
+        target: &mut impl Surface,
+        area: glium::Rect,
+    ) -> anyhow::Result<()> {
+        if !self.visible {
+            return Ok(());
+        }
+        let params = glium::DrawParameters {
+            blend: glium::Blend::alpha_blending(),
+            viewport: Some(glium::Rect {
+                width: WIDTH.min(area.width / 3),
+                height: HEIGHT.min(area.height),
+                ..area
+            }),
+            ..Default::default()
+        };
+        let mut draw = |points: &[Point], shape, color: [f32; 4]| -> anyhow::Result<()> {
+            let vertices = glium::VertexBuffer::new(facade, points)?;
+            let indices = glium::index::NoIndices(shape);
+            let uniforms = uniform! { color: color };
+            target.draw(&vertices, indices, &self.program, &uniforms, &params)?;
+            Ok(())
+        };
+
+        let corners = [[-1.0, -1.0], [1.0, -1.0], [-1.0, 1.0], [1.0, 1.0]];
+        let background = corners.map(|position| Point { position });
+        draw(
+            &background,
+            PrimitiveType::TriangleStrip,
+            [0.0, 0.0, 0.0, 0.6],
+        )?;
+        // where 60 and 30 fps would be
+        for fps in [60.0, 30.0] {
+            let y = height(1000.0 / fps);
+            let line = [-1.0, 1.0].map(|x| Point { position: [x, y] });
+            draw(&line, PrimitiveType::LinesList, [0.5, 0.5, 0.5, 1.0])?;
+        }
+        for (intervals, color) in self.intervals.iter().zip(COLORS) {
+            if intervals.len() > 1 {
+                draw(&line(intervals), PrimitiveType::LineStrip, color)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Adds an interval, dropping the oldest beyond [`SAMPLES`].
+fn push(intervals: &mut VecDeque<f32>, interval: Duration) {
+    if intervals.len() == SAMPLES {
+        intervals.pop_front();
+    }
+    intervals.push_back(interval.as_secs_f32() * 1000.0);
+}
+
+/// The points of a millisecond interval line in normalized device coordinates, the newest at
+/// the right edge.
+fn line(intervals: &VecDeque<f32>) -> Vec<Point> {
+    let step = 2.0 / (SAMPLES - 1) as f32;
+    let left = 1.0 - (intervals.len() - 1) as f32 * step;
+    intervals
+        .iter()
+        .enumerate()
+        .map(|(index, &ms)| Point {
+            position: [left + index as f32 * step, height(ms)],
+        })
+        .collect()
+}
+
+fn height(ms: f32) -> f32 {
+    (ms / MAX_MS).min(1.0) * 2.0 - 1.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_end_at_the_right_edge_and_clip_spikes() {
+        let mut intervals = VecDeque::new();
+        for ms in [16.0, 16.0, 250.0] {
+            push(&mut intervals, Duration::from_secs_f32(ms / 1000.0));
+        }
+        let points = line(&intervals);
+        let last = points.last().unwrap().position;
+        assert!((last[0] - 1.0).abs() < 1e-6);
+        assert_eq!(last[1], 1.0);
+        assert!(points[0].position[1] < -0.6);
+
+        for _ in 0..SAMPLES {
+            push(&mut intervals, Duration::from_millis(16));
+        }
+        assert_eq!(intervals.len(), SAMPLES);
+        assert!((line(&intervals)[0].position[0] + 1.0).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,55 @@
+//! Writes the composited image to a V4L2 output device, such as a v4l2loopback node that other
+//! applications can then capture from like from a webcam.
+
+use anyhow::Context;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+use v4l::{video::Output, Device, Format, FourCC};
+
+use crate::output::{self, OutputSink};
+
+pub struct Loopback {
+    dev: Device,
+    path: PathBuf,
+    /// The resolution the device's format is set to, once it has been.
+    size: Option<(u32, u32)>,
+}
+
+impl Loopback {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let dev =
+            Device::with_path(path).with_context(|| format!("Couldn't open {}", path.display()))?;
+        Ok(Loopback {
+            dev,
+            path: path.to_owned(),
+            size: None,
+        })
+    }
+}
+
+impl OutputSink for Loopback {
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn write(&mut self, rgba: &[u8], width: u32, height: u32) -> anyhow::Result<()> {
+        if self.size != Some((width, height)) {
+            let requested = Format::new(width, height, FourCC::new(b"RGB3"));
+            let format =
+                Output::set_format(&self.dev, &requested).context("Couldn't set format")?;
+            anyhow::ensure!(
+                (format.width, format.height, format.fourcc) == (width, height, requested.fourcc),
+                "device only accepts {format}"
+            );
+            println!(
+                "\nWriting {width}x{height} RGB3 frames to {}",
+                self.path.display()
+            );
+            self.size = Some((width, height));
+        }
+        self.dev.write_all(&output::top_down_rgb(rgba, width))?;
+        Ok(())
+    }
+}
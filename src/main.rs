@@ -1,312 +1,3151 @@
-use anyhow::Context;
-use clap::Parser;
-use glium::{implement_vertex, index::PrimitiveType, program, uniform, Surface};
-use jpeg_decoder as jpeg;
+use clap::{parser::ValueSource, ArgMatches, CommandFactory, FromArgMatches, Parser};
+use glium::{implement_vertex, index::PrimitiveType, uniform, Surface};
 use std::{
-    sync::{Arc, RwLock},
-    thread,
-    time::Instant,
-};
-use v4l::{
-    buffer::Type,
-    io::traits::CaptureStream,
-    prelude::*,
-    video::{capture::Parameters, Capture},
-    Format, FourCC,
+    collections::VecDeque,
+    fmt,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
 };
+use winit::keyboard::KeyCode;
+
+use camera::{cam, Camera, CaptureOptions, Frame, PixelFormat};
+use scheme::{ChannelMask, ColorScheme};
+use source::{FileSource, SocketSource};
+
+mod calibration;
+mod camera;
+mod clahe;
+mod color;
+mod convert;
+mod cpu;
+mod disparity;
+mod display;
+mod error;
+mod graph;
+mod loopback;
+mod metrics;
+mod output;
+mod panel;
+mod pipe;
+mod recording;
+mod renderer;
+mod scheme;
+mod second_window;
+mod settings;
+mod shaders;
+mod snapshot;
+mod source;
+mod stereo_image;
+mod sync_gpio;
+mod triple_buffer;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Mode {
+    /// Both cameras combined through the color scheme's channel masks
+    Anaglyph,
+    /// Several full-color views interpolated between the cameras, tiled for lenticular or
+    /// parallax-barrier panels
+    Multiview,
+    /// A rough false-color map of the horizontal disparity between the cameras, to check a
+    /// rig's baseline and eye order; toggle at runtime with D
+    Disparity,
+    /// Both cameras in full color on top of each other, camera2 translucent, to check their
+    /// alignment in 2D; the arrow keys move camera2 like J/L/I/K
+    Overlay,
+    /// Both cameras in full color next to each other, camera1 on the left, for headsets and
+    /// displays that take side-by-side stereo
+    SideBySide,
+}
+
+/// How interlaced input frames are turned into progressive ones, in the fragment shader on the
+/// current frame only, so neither adds latency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Deinterlace {
+    /// Show only the even field, each line twice; no combing, half the vertical resolution
+    Bob,
+    /// Average each line with the next one from the other field; keeps more detail in still
+    /// areas, moving edges show both fields at half strength
+    Blend,
+}
+
+/// Where a camera's frames come from.
+#[derive(Debug, Clone, Copy)]
+enum Input<'a> {
+    Device(&'a str),
+    Socket(&'a Path),
+    File(&'a Path),
+}
+
+impl fmt::Display for Input<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Input::Device(path) => f.write_str(path),
+            Input::Socket(path) | Input::File(path) => path.display().fmt(f),
+        }
+    }
+}
+
+/// How the camera image is fitted into the window when their aspect ratios differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Fit {
+    /// Fill the window, distorting the image (and with it the disparities)
+    Stretch,
+    /// Show the whole image with black bars
+    Contain,
+    /// Fill the window, cropping the image's edges
+    Cover,
+}
+
+/// What the camera images show where they're sampled outside their edges, e.g. after shifting
+/// them for convergence or alignment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Edge {
+    /// Repeat the outermost row or column of texels
+    Clamp,
+    /// Black (transparent with --transparent)
+    Border,
+    /// Tile the image, so the opposite edge shows up as a sliver
+    Wrap,
+}
+
+impl Edge {
+    fn wrap_function(self) -> glium::uniforms::SamplerWrapFunction {
+        use glium::uniforms::SamplerWrapFunction;
+        match self {
+            Edge::Clamp => SamplerWrapFunction::Clamp,
+            Edge::Border => SamplerWrapFunction::BorderClamp,
+            Edge::Wrap => SamplerWrapFunction::Repeat,
+        }
+    }
+}
+
+/// How eagerly the event loop renders, trading latency for power.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum PowerMode {
+    /// Render again as soon as a frame is done
+    Performance,
+    /// Render at the monitor's refresh rate, sleeping in between
+    Balanced,
+    /// Only render when a camera has a new frame or there's input
+    PowerSave,
+}
+
+/// The GL internal format camera frames are uploaded into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum TextureFormat {
+    /// Whatever the driver picks for 8-bit RGB
+    Auto,
+    /// 8 bits per channel with padding for alpha, which most GPUs store anyway
+    Rgba8,
+    /// 8 bits per channel without alpha
+    Rgb8,
+    /// 5 bits per channel, half the memory for huge resolutions at the cost of banding
+    Rgb5,
+    /// Half floats, so the shaders' gain and gamma work on more precise values
+    Rgb16f,
+}
+
+impl TextureFormat {
+    fn internal(self) -> Option<glium::texture::UncompressedFloatFormat> {
+        use glium::texture::UncompressedFloatFormat;
+        match self {
+            TextureFormat::Auto => None,
+            TextureFormat::Rgba8 => Some(UncompressedFloatFormat::U8U8U8U8),
+            TextureFormat::Rgb8 => Some(UncompressedFloatFormat::U8U8U8),
+            TextureFormat::Rgb5 => Some(UncompressedFloatFormat::U5U5U5),
+            TextureFormat::Rgb16f => Some(UncompressedFloatFormat::F16F16F16),
+        }
+    }
+
+    /// Fails if the driver behind `facade` can't create textures in this format.
+    fn check(self, facade: &impl glium::backend::Facade) -> anyhow::Result<()> {
+        if let Some(format) = self.internal() {
+            anyhow::ensure!(
+                format.is_supported(facade.get_context()),
+                "the driver doesn't support --texture-format {}",
+                clap::ValueEnum::to_possible_value(&self)
+                    .unwrap()
+                    .get_name()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Which frames the eyes show when the cameras deliver them at different times or rates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum SyncStrategy {
+    /// Always show each camera's newest frame, repeating the slower one's until it has another
+    Free,
+    /// Only move on once both cameras have a new frame, so both eyes change together
+    Hold,
+    /// Only take a new frame if it's at least as close in time to the other camera's newest
+    /// frame as the one shown
+    Closest,
+}
+
+impl SyncStrategy {
+    /// How long an eye is kept on a frame waiting for the other camera, in case that stalled.
+    const MAX_HOLD: Duration = Duration::from_millis(200);
+
+    /// Which eyes should switch to their camera's newest frame, given when the frames that are
+    /// shown and the newest ones were captured.
+    fn take(self, shown: [Option<Instant>; 2], newest: [Option<Instant>; 2]) -> [bool; 2] {
+        let apart = |a: Instant, b: Instant| a.max(b) - a.min(b);
+        [0, 1].map(|eye| {
+            let other = 1 - eye;
+            let new = newest[eye] != shown[eye];
+            let held = || shown[eye].map_or(true, |shown| shown.elapsed() > Self::MAX_HOLD);
+            match (self, shown[eye], newest[eye], newest[other]) {
+                (SyncStrategy::Free, ..) => true,
+                (SyncStrategy::Hold, ..) => new && (newest[other] != shown[other] || held()),
+                (SyncStrategy::Closest, Some(shown), Some(next), Some(other)) => {
+                    new && (apart(next, other) <= apart(shown, other) || held())
+                }
+                (SyncStrategy::Closest, ..) => new,
+            }
+        })
+    }
+}
+
+/// A color vision deficiency to simulate, with the full-severity matrices of Machado et al.
+/// (2009).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum ColorVision {
+    /// No working long-wavelength (red) cones
+    Protanopia,
+    /// No working medium-wavelength (green) cones
+    Deuteranopia,
+    /// No working short-wavelength (blue) cones
+    Tritanopia,
+}
+
+impl ColorVision {
+    /// The transform of linear RGB, by columns as GLSL's `mat3` takes it.
+    fn matrix(self) -> [[f32; 3]; 3] {
+        let rows = match self {
+            ColorVision::Protanopia => [
+                [0.152286, 1.052583, -0.204868],
+                [0.114503, 0.786281, 0.099216],
+                [-0.003882, -0.048116, 1.051998],
+            ],
+            ColorVision::Deuteranopia => [
+                [0.367322, 0.860646, -0.227968],
+                [0.280085, 0.672501, 0.047413],
+                [-0.011820, 0.042940, 0.968881],
+            ],
+            ColorVision::Tritanopia => [
+                [1.255528, -0.076749, -0.178779],
+                [-0.078411, 0.930809, 0.147602],
+                [0.004733, 0.691367, 0.303900],
+            ],
+        };
+        [0, 1, 2].map(|column| rows.map(|row| row[column]))
+    }
+}
+
+impl Fit {
+    /// Scale of the full-viewport quad that fits an image of `size` into `viewport`.
+    fn scale(self, (width, height): (u32, u32), viewport: (u32, u32)) -> [f32; 2] {
+        let image = width as f32 / height as f32;
+        let viewport = viewport.0 as f32 / viewport.1 as f32;
+        // > 1 when the image is wider than the viewport
+        let ratio = image / viewport;
+        match self {
+            Fit::Stretch => [1.0, 1.0],
+            Fit::Contain if ratio > 1.0 => [1.0, 1.0 / ratio],
+            Fit::Cover if ratio < 1.0 => [1.0, 1.0 / ratio],
+            Fit::Contain | Fit::Cover => [ratio, 1.0],
+        }
+    }
+}
 
 #[derive(Debug, Parser)]
 struct Cli {
+    /// Device path of camera1, or `name:<card name>` or `serial:<USB serial number>` to find
+    /// it by what it is
     #[clap(default_value = "/dev/video0")]
     camera1: String,
     #[clap(long)]
     camera1_flip_y: bool,
+    /// Red, green and blue gains for camera1, as printed by the W key
+    #[clap(long, value_delimiter = ',', num_args = 3, default_values_t = [1.0, 1.0, 1.0])]
+    camera1_gains: Vec<f32>,
+    /// Receive camera1 frames over this Unix socket instead (see `source::SocketSource`)
+    #[clap(long)]
+    camera1_socket: Option<PathBuf>,
+    /// Play this video file as camera1 instead, looping at the end (needs `ffmpeg`)
+    #[clap(long, conflicts_with = "camera1_socket")]
+    camera1_file: Option<PathBuf>,
+    /// Rotation of camera1 about the image center in degrees, to correct roll; multiples of 90
+    /// are left to the camera's rotate control when it has one
+    #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    camera1_roll: f32,
+    /// Magnification of camera1 about the image center, to make objects the same size in both
+    /// eyes when the lenses' fields of view differ
+    #[clap(long, default_value_t = 1.0)]
+    camera1_scale: f32,
+    /// Deinterlace camera1's frames, for capture cards delivering interlaced video
+    #[clap(long, value_enum)]
+    camera1_deinterlace: Option<Deinterlace>,
+    /// Capture raw frames with this Bayer pattern from camera1 and demosaic them on the GPU;
+    /// raw Bayer formats are picked up without it, but some drivers report the wrong pattern
+    #[clap(
+        long,
+        value_enum,
+        ignore_case = true,
+        conflicts_with = "camera1_deinterlace"
+    )]
+    camera1_bayer: Option<camera::Bayer>,
+    /// Only decode every Nth buffer camera1 delivers, to save CPU when it runs faster than
+    /// needed; the others are still dequeued so the driver's queue doesn't back up
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    camera1_decimate: u32,
+    /// Capture only this rectangle of camera1, given as x,y,width,height in pixels from its
+    /// top left, to save bandwidth and decoding; cropped in the shader if the camera can't,
+    /// which --cpu-render doesn't
+    #[clap(long, value_delimiter = ',', num_args = 4)]
+    camera1_hw_crop: Option<Vec<u32>>,
 
+    /// Device path of camera2, or `name:` or `serial:` like camera1
     #[clap(default_value = "/dev/video2")]
     camera2: String,
     #[clap(long)]
     camera2_flip_y: bool,
+    /// Red, green and blue gains for camera2, as printed by the W key
+    #[clap(long, value_delimiter = ',', num_args = 3, default_values_t = [1.0, 1.0, 1.0])]
+    camera2_gains: Vec<f32>,
+    /// Receive camera2 frames over this Unix socket instead (see `source::SocketSource`)
+    #[clap(long)]
+    camera2_socket: Option<PathBuf>,
+    /// Play this video file as camera2 instead, looping at the end (needs `ffmpeg`)
+    #[clap(long, conflicts_with = "camera2_socket")]
+    camera2_file: Option<PathBuf>,
+    /// Rotation of camera2 about the image center in degrees, to correct roll; multiples of 90
+    /// are left to the camera's rotate control when it has one
+    #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    camera2_roll: f32,
+    /// Magnification of camera2 about the image center, to make objects the same size in both
+    /// eyes when the lenses' fields of view differ
+    #[clap(long, default_value_t = 1.0)]
+    camera2_scale: f32,
+    /// Deinterlace camera2's frames, for capture cards delivering interlaced video
+    #[clap(long, value_enum)]
+    camera2_deinterlace: Option<Deinterlace>,
+    /// Capture raw frames with this Bayer pattern from camera2 and demosaic them on the GPU;
+    /// raw Bayer formats are picked up without it, but some drivers report the wrong pattern
+    #[clap(
+        long,
+        value_enum,
+        ignore_case = true,
+        conflicts_with = "camera2_deinterlace"
+    )]
+    camera2_bayer: Option<camera::Bayer>,
+    /// Only decode every Nth buffer camera2 delivers, to save CPU when it runs faster than
+    /// needed; the others are still dequeued so the driver's queue doesn't back up
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    camera2_decimate: u32,
+    /// Like --camera1-hw-crop for camera2
+    #[clap(long, value_delimiter = ',', num_args = 4)]
+    camera2_hw_crop: Option<Vec<u32>>,
+    /// Horizontal shift of camera2 in texels, adjustable at runtime with J/L
+    #[clap(long, default_value_t = 0, allow_negative_numbers = true)]
+    camera2_offset_x: i32,
+    /// Vertical shift of camera2 in texels, adjustable at runtime with I/K
+    #[clap(long, default_value_t = 0, allow_negative_numbers = true)]
+    camera2_offset_y: i32,
 
     #[clap(long)]
     flip_x: bool,
 
+    /// Turn the whole output upside down, for projectors mounted on the ceiling; unlike the
+    /// cameras' flips this happens after all their shifts, so both eyes are flipped together
+    #[clap(long)]
+    flip_output_y: bool,
+
+    /// For rigs facing the viewer: mirrors both cameras horizontally (like --flip-x) and
+    /// shows camera1 to the right eye and camera2 to the left eye
+    #[clap(long)]
+    selfie: bool,
+
+    #[clap(long, value_enum, default_value_t = Mode::Anaglyph)]
+    mode: Mode,
+
+    /// Also show the cameras in this mode in a second window, fullscreen on the second monitor
+    /// if there is one; anaglyph (without crosstalk cancellation or comfort) or side-by-side
+    #[clap(long, value_enum)]
+    window2_mode: Option<Mode>,
+
+    /// How to deal with camera images whose aspect ratio differs from the window's
+    #[clap(long, value_enum, default_value_t = Fit::Stretch)]
+    fit: Fit,
+
+    /// Only draw into this rectangle of the window, given as x,y,width,height in pixels from
+    /// its top left, leaving the rest untouched
+    #[clap(long, value_delimiter = ',', num_args = 4)]
+    viewport: Option<Vec<u32>>,
+
+    /// What to show outside the camera images' edges where they're shifted or rotated away
+    #[clap(long, value_enum, default_value_t = Edge::Border)]
+    edge: Edge,
+
+    /// Darken both eyes alike toward the edges of the image by up to this much, to hide
+    /// mismatched dark corners of cheap lenses; 0 disables it and 1 fades the corners to black
+    #[clap(long, default_value_t = 0.0, value_parser = parse_finite)]
+    vignette: f32,
+    /// Distance from the center where --vignette starts, with the middle of the edges at 1
+    #[clap(long, default_value_t = 0.8, value_parser = parse_finite)]
+    vignette_radius: f32,
+
+    /// Opacity of camera2 over camera1 in overlay mode
+    #[clap(long, default_value_t = 0.5)]
+    overlay_opacity: f32,
+
+    /// Number of views generated in multiview mode, from camera1 to camera2
+    #[clap(long, default_value_t = 5)]
+    views: u32,
+
+    /// How many views are tiled side by side before starting a new row (default: all)
+    #[clap(long)]
+    view_columns: Option<u32>,
+
+    /// Multiplier for all colors of both cameras
+    #[clap(long, default_value_t = 1.0)]
+    brightness: f32,
+
+    /// Gamma applied to both cameras after brightness; above 1 brightens the midtones
+    #[clap(long, default_value_t = 1.0)]
+    gamma: f32,
+
+    /// How much of the previous frame is kept in each new one, from 0 to 1, to average out
+    /// sensor noise in low light at the cost of motion blur
+    #[clap(long)]
+    denoise: Option<f32>,
+
+    /// Show the mean of each camera's last N frames instead, for a true average over a fixed
+    /// window in static low-light scenes; costs N draws per new frame
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..=64))]
+    frame_average: Option<u32>,
+    /// Like --frame-average for camera1 only, overriding it
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..=64))]
+    camera1_frame_average: Option<u32>,
+    /// Like --frame-average for camera2 only, overriding it
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..=64))]
+    camera2_frame_average: Option<u32>,
+
+    /// Bring out detail in shadows and highlights by equalizing the histograms of 8x8 tiles of
+    /// each camera, stretching contrast up to this multiple of the mean; costs a few ms of CPU
+    /// per frame and camera at 1080p, on the render thread
+    #[clap(long, value_name = "CLIP_LIMIT", num_args = 0..=1, default_missing_value = "2")]
+    clahe: Option<f32>,
+
+    /// Cross-fade each camera from its previous frame to its current one over the time between
+    /// them, smoothing motion from cameras slower than the display at the cost of up to a frame
+    /// of latency
+    #[clap(long, conflicts_with = "denoise")]
+    interpolate: bool,
+
+    /// When each eye moves on to its camera's next frame, for cameras running at different
+    /// rates
+    #[clap(long, value_enum, default_value_t = SyncStrategy::Free)]
+    sync_strategy: SyncStrategy,
+
+    /// How eagerly to render; power-save suits mostly static scenes on battery-powered rigs
+    #[clap(long, value_enum, default_value_t = PowerMode::Performance)]
+    power_mode: PowerMode,
+
+    /// Brightness of camera1 relative to camera2, to balance glasses whose lenses differ in
+    /// density; adjustable at runtime with 1 and 2
+    #[clap(long, default_value_t = 1.0)]
+    eye1_gain: f32,
+
+    /// Brightness of camera2 relative to camera1; adjustable at runtime with 9 and 0
+    #[clap(long, default_value_t = 1.0)]
+    eye2_gain: f32,
+
+    /// Fraction of the other eye's image taken out of each eye, to cancel the ghosting that
+    /// leaks through the glasses; adjustable at runtime with [ and ]
+    #[clap(long, default_value_t = 0.0)]
+    crosstalk: f32,
+
+    /// Mix both eyes' full color into the output through Dubois' least-squares matrices for
+    /// --color-scheme instead of masking channels, for the least ghosting; --crosstalk is ignored
+    #[clap(long)]
+    dubois: bool,
+
+    /// Fraction of each eye's luminance blended into the other eye, from 0 for full separation
+    /// to 1 for an almost flat image, for less strain in long sessions; adjustable at runtime
+    /// with , and .
+    #[clap(long, default_value_t = 0.0)]
+    comfort: f32,
+
+    /// Brighten the darker camera every frame so both have the same average luminance, to
+    /// follow auto-exposure differences; the optional value is how much of the previous
+    /// correction is kept each frame, to keep it from flickering
+    #[clap(long, num_args = 0..=1, default_missing_value = "0.95")]
+    auto_match: Option<f32>,
+
+    /// Color filters of the glasses, left lens first; cycle at runtime with C
+    #[clap(long, value_enum, default_value_t = ColorScheme::RedCyan)]
+    color_scheme: ColorScheme,
+
+    /// Start by showing LEFT and RIGHT in the colors of the left and right lens until a key is
+    /// pressed, to check the glasses are the right way round and match --color-scheme
+    #[clap(long)]
+    self_test: bool,
+    /// Start by showing each lens color full-screen and asking which eye sees it, then send
+    /// each camera to the eye it's meant for however the glasses are worn; before --self-test
+    #[clap(long)]
+    verify_glasses: bool,
+    /// Experimental: swap the eyes on startup if the disparity between the cameras' first frames
+    /// says they're the wrong way round, keeping them if it's unclear
+    #[clap(long)]
+    auto_eye_order: bool,
+
+    /// Show the composited image as someone with this color vision deficiency would see it
+    #[clap(long, value_enum)]
+    simulate_cvd: Option<ColorVision>,
+
+    /// Horizontal parallax between the eyes in texels, adjustable at runtime with the arrow
+    /// keys; positive values pull the scene toward the viewer
+    #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    convergence: f32,
+
+    /// Shift of camera2 alone in texels, adjustable at runtime with the up and down arrow
+    /// keys; positive values push the whole scene behind the screen, unlike --convergence it
+    /// doesn't keep the image centered
+    #[clap(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    depth_bias: f32,
+
     #[clap(long, default_value_t = 1280)]
     width: u32,
 
     #[clap(long, default_value_t = 720)]
     height: u32,
-}
 
-type ImageBuffer = Arc<RwLock<Vec<u8>>>;
+    /// Fail instead of warning when a camera can't deliver the requested resolution
+    #[clap(long)]
+    strict_resolution: bool,
 
-fn main() -> anyhow::Result<()> {
-    let args = Cli::parse();
+    /// Kind of buffers to capture into; mmap is used where the one asked for isn't supported
+    #[clap(long, value_enum, default_value_t = camera::IoMethod::Mmap)]
+    io_method: camera::IoMethod,
 
-    let (raw_image1, format1) = cam(&args.camera1, args.width, args.height)?;
-    let (raw_image2, format2) = cam(&args.camera2, args.width, args.height)?;
+    /// How many more times to try setting a camera's format and starting its stream when the
+    /// device is busy, e.g. right after a previous instance exited
+    #[clap(long, default_value_t = 5)]
+    open_retries: u32,
 
-    let event_loop = winit::event_loop::EventLoop::new()?;
-    let (window, display) = glium::backend::glutin::SimpleWindowBuilder::new().build(&event_loop);
-    window.request_redraw();
-    window.set_fullscreen(Some(winit::window::Fullscreen::Borderless(None)));
-    window.focus_window();
-    event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
+    /// How long to wait before each retry of a busy camera, doubling every time
+    #[clap(long, value_name = "MS", default_value_t = 200)]
+    open_retry_delay_ms: u64,
 
-    // the following OpenGL code is inspired by
-    // <https://github.com/raymanfx/libv4l-rs/blob/ced9df0bb2ab3c1b03783536fceb209a630d23c8/examples/glium.rs>
-    // which is licensed under the MIT license
-    let vertex_buffer = {
-        #[derive(Copy, Clone)]
-        struct Vertex {
-            position: [f32; 2],
-            tex_coords: [f32; 2],
-        }
+    /// Use both capture nodes of a stereo camera that exposes one per sensor, given either of
+    /// them; the lower-numbered node is camera1 and is started first
+    #[clap(long, conflicts_with_all = ["camera1", "camera2", "pick", "mpo", "sbs"])]
+    stereo_camera: Option<String>,
 
-        implement_vertex!(Vertex, position, tex_coords);
+    /// Choose both cameras from a menu of the detected capture devices before starting
+    #[clap(long, conflicts_with_all = ["mpo", "sbs"])]
+    pick: bool,
 
-        glium::VertexBuffer::new(
-            &display,
-            &[
-                Vertex {
-                    position: [-1.0, -1.0],
-                    tex_coords: [0.0, 0.0],
-                },
-                Vertex {
-                    position: [-1.0, 1.0],
-                    tex_coords: [0.0, 1.0],
-                },
-                Vertex {
-                    position: [1.0, 1.0],
-                    tex_coords: [1.0, 1.0],
-                },
-                Vertex {
-                    position: [1.0, -1.0],
-                    tex_coords: [1.0, 0.0],
-                },
-            ],
-        )
-        .unwrap()
-    };
+    /// Run each camera's capture thread and the render thread on a core of their own
+    #[clap(long)]
+    pin_threads: bool,
 
-    let index_buffer =
-        glium::IndexBuffer::new(&display, PrimitiveType::TriangleStrip, &[1u16, 2, 0, 3]).unwrap();
+    /// Log the sequence number, timestamp and flags of every buffer the cameras deliver
+    #[clap(long)]
+    verbose: bool,
 
-    let program_camera1 = program!(&display,
-        140 => {
-            vertex: "
-                #version 140
-                uniform mat4 matrix;
-                in vec2 position;
-                in vec2 tex_coords;
-                out vec2 v_tex_coords;
-                void main() {{
-                    gl_Position = matrix * vec4(position, 0.0, 1.0);
-                    v_tex_coords = tex_coords;
-                }}
-            ",
+    /// Print the formats, resolutions, frame rates and controls this device supports, then exit
+    #[clap(long, value_name = "DEVICE")]
+    probe: Option<String>,
 
-            fragment: &format!("
-                #version 140
-                uniform sampler2D tex;
-                in vec2 v_tex_coords;
-                out vec4 f_color;
+    /// Time decoding this JPEG the way MJPEG frames are decoded, with --gpu-color-convert
+    /// leaving it in YCbCr, then exit without opening any camera or window
+    #[clap(long, value_name = "FILE")]
+    benchmark_decode: Option<PathBuf>,
 
-                void main() {{
-                    vec2 new_tex_coords = v_tex_coords;
-                    {flip_y}
-                    {flip_x}
-                    f_color = texture(tex, new_tex_coords);
-                }}
-            ", flip_x=if args.flip_x {
-                "new_tex_coords.x = 1.0 - new_tex_coords.x;"
-            } else {
-                ""
-            }, flip_y=if args.camera1_flip_y {
-                "new_tex_coords.y = 1.0 - new_tex_coords.y;
-                new_tex_coords.x = 1.0 - new_tex_coords.x;"
-            } else {
-                ""
-            }),
-        },
-    )
-    .unwrap();
+    /// How many times --benchmark-decode decodes the file
+    #[clap(long, default_value_t = 100)]
+    benchmark_iterations: u32,
 
-    let program_camera2 = program!(&display,
-        140 => {
-            vertex: "
-                #version 140
-                uniform mat4 matrix;
-                in vec2 position;
-                in vec2 tex_coords;
-                out vec2 v_tex_coords;
-                void main() {
-                    gl_Position = matrix * vec4(position, 0.0, 1.0);
-                    v_tex_coords = tex_coords;
-                }
-            ",
+    /// Size of the thread pool both cameras decode MJPEG frames on, each frame split across it;
+    /// defaults to the number of cores
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    decode_threads: Option<u32>,
 
-            fragment: &format!("
-                #version 140
-                uniform sampler2D tex;
-                in vec2 v_tex_coords;
-                out vec4 f_color;
+    /// Print the shader sources generated for both cameras with the given options, then exit;
+    /// raw Bayer formats are only known once a camera is open, so only --camera1-bayer and
+    /// --camera2-bayer are taken into account
+    #[clap(long)]
+    dump_shaders: bool,
 
-                void main() {{
-                    vec2 new_tex_coords = v_tex_coords;
-                    {flip_y}
-                    {flip_x}
-                    f_color = texture(tex, new_tex_coords);
-                }}
-            ", flip_x=if args.flip_x {
-                "new_tex_coords.x = 1.0 - new_tex_coords.x;"
-            } else {
-                ""
-            }, flip_y=if args.camera2_flip_y {
-                "new_tex_coords.y = 1.0 - new_tex_coords.y;
-                new_tex_coords.x = 1.0 - new_tex_coords.x;"
-            } else {
-                ""
-            }),
-        },
-    )
-    .unwrap();
+    /// Open both cameras and negotiate their format, then exit without capturing
+    #[clap(long)]
+    dry_run: bool,
 
-    event_loop.run(move |event, elwt| {
-        let t0 = Instant::now();
+    /// Clicking in the window has both cameras autofocus on what's under the cursor, for
+    /// cameras with UVC region of interest controls
+    #[clap(long)]
+    focus_on_click: bool,
 
-        let mut target = display.draw();
-        target.clear_color(0.0, 0.0, 0.0, 0.0);
+    /// Put both cameras into triggered capture and trigger them together every frame, so both
+    /// eyes are exposed at the same instant; needs drivers with trigger controls
+    #[clap(long)]
+    trigger_mode: bool,
 
-        let image_to_uniforms = |buffer: &ImageBuffer, format: Format| {
-            let data: Vec<u8> = buffer.read().unwrap().clone();
-            if data.is_empty() {
-                return None;
-            }
+    /// Flip this GPIO line with every new stereo pair presented, for external hardware to sync
+    /// to; needs the gpio feature and does nothing without a GPIO chip
+    #[clap(long, value_name = "PIN")]
+    sync_gpio: Option<u32>,
+    /// The GPIO chip --sync-gpio's line is on
+    #[clap(long, default_value = "/dev/gpiochip0")]
+    sync_gpio_chip: PathBuf,
 
-            let image = glium::texture::RawImage2d::from_raw_rgb_reversed(
-                &data,
-                (format.width, format.height),
-            );
-            let opengl_texture = glium::texture::Texture2d::new(&display, image).unwrap();
-            let uniforms = uniform! {
-                matrix: [
-                    [1.0, 0.0, 0.0, 0.0],
-                    [0.0, 1.0, 0.0, 0.0],
-                    [0.0, 0.0, 1.0, 0.0],
-                    [0.0, 0.0, 0.0, 1.0f32]
-                ],
-                tex: opengl_texture
-            };
-            Some(uniforms)
-        };
+    /// Restart a camera's capture when it hasn't produced a frame for this long
+    #[clap(long)]
+    stall_timeout_ms: Option<u64>,
 
-        if let Some(uniforms) = image_to_uniforms(&raw_image1, format1) {
-            target
-                .draw(
-                    &vertex_buffer,
-                    &index_buffer,
-                    &program_camera1,
-                    &uniforms,
-                    &glium::DrawParameters {
-                        blend: glium::Blend::alpha_blending(),
-                        color_mask: (true, false, false, true),
-                        ..Default::default()
-                    },
-                )
-                .unwrap();
-        }
+    /// Show moving color bars once neither camera has delivered a frame for this many seconds,
+    /// so it's clear the display itself is still alive
+    #[clap(long, num_args = 0..=1, default_missing_value = "3")]
+    no_signal_pattern: Option<f32>,
 
-        if let Some(uniforms) = image_to_uniforms(&raw_image2, format2) {
-            target
-                .draw(
-                    &vertex_buffer,
-                    &index_buffer,
-                    &program_camera2,
-                    &uniforms,
-                    &glium::DrawParameters {
-                        blend: glium::Blend::alpha_blending(),
-                        color_mask: (false, true, true, true),
-                        ..Default::default()
-                    },
-                )
-                .unwrap();
-        }
+    /// Show nothing of a camera rather than a frame captured longer than this many milliseconds
+    /// ago, for teleoperation; how many frames that dropped is printed on exit
+    #[clap(long, value_name = "MS")]
+    max_latency_ms: Option<u64>,
 
-        let t1 = Instant::now();
+    /// In anaglyph mode, show the one camera that has delivered a frame within this many
+    /// seconds in full color on its own, until the other one has too
+    #[clap(long, num_args = 0..=1, default_missing_value = "1")]
+    fallback_2d: Option<f32>,
 
-        target.finish().unwrap();
+    /// Composite a single anaglyph offscreen, write it to --output and exit
+    #[clap(long, requires = "output")]
+    once: bool,
+    /// Where --once writes the anaglyph to, as a binary PPM
+    #[clap(long, requires = "once")]
+    output: Option<PathBuf>,
+    /// Seconds --once waits for both cameras' first frame
+    #[clap(long, default_value_t = 5.0)]
+    once_timeout: f32,
 
-        if let winit::event::Event::WindowEvent {
-            event: winit::event::WindowEvent::CloseRequested,
-            ..
-        } = event
-        {
-            elwt.exit();
-        }
+    /// Where the S key saves the current convergence, depth bias, camera2 offset, rolls and
+    /// scales
+    #[clap(long)]
+    save_calibration: Option<PathBuf>,
+    /// Start with the alignment saved by --save-calibration; flags given on the command line
+    /// take precedence
+    #[clap(long)]
+    load_calibration: Option<PathBuf>,
 
-        print!(
-            "\rms: {}\t (buffer) + {}\t (UI)",
-            t1.duration_since(t0).as_millis(),
-            t0.elapsed().as_millis()
-        );
-    })?;
-    Ok(())
-}
+    /// Show the stereo pair stored in an MPO file instead of the cameras
+    #[clap(long, conflicts_with = "sbs")]
+    mpo: Option<PathBuf>,
+
+    /// Show a side-by-side stereo JPEG instead of the cameras
+    #[clap(long)]
+    sbs: Option<PathBuf>,
+
+    /// Print the 50th and 99th percentile of the time from capture to presentation every second
+    #[clap(long)]
+    print_latency: bool,
+
+    /// Plot the intervals between the last few hundred frames of each camera and the window
+    /// in the bottom left corner; toggle at runtime with G
+    #[clap(long)]
+    graph: bool,
+
+    /// Print how long each frame took to stderr, on one line redrawn in place on a terminal
+    #[clap(long)]
+    stats: bool,
+
+    /// Also write every composited frame to this V4L2 output device, e.g. a v4l2loopback node
+    /// for video conferencing apps or OBS to capture from; repeat for several devices
+    #[clap(long, conflicts_with = "cpu_render")]
+    output_v4l2: Vec<PathBuf>,
+    /// Also write every composited frame to this named pipe, or to stdout for `-`, for ffmpeg
+    /// or other tools to read; other output then goes to stderr
+    #[clap(long, value_name = "FILE", conflicts_with = "cpu_render")]
+    output_pipe: Option<PathBuf>,
+    /// How --output-pipe encodes the frames; raw RGB is 3 bytes per pixel, top row first, with
+    /// nothing between frames, MJPEG is one JPEG after another and needs `ffmpeg`
+    #[clap(long, value_enum, default_value_t = pipe::PipeFormat::Rgb)]
+    output_pipe_format: pipe::PipeFormat,
+    /// JPEG quality from 0 to 100 of --output-pipe-format mjpeg, trading bandwidth for detail;
+    /// --record-stereo is lossless and not affected
+    #[clap(
+        long,
+        requires = "output_pipe",
+        value_parser = clap::value_parser!(u8).range(0..=100)
+    )]
+    output_quality: Option<u8>,
+    /// Render offscreen without a window, only for --output-pipe and --output-v4l2
+    #[clap(long, conflicts_with_all = ["once", "cpu_render"])]
+    headless: bool,
+    /// Width of the frames written to --output-v4l2, --output-pipe and --once, scaled by the
+    /// GPU from the window's size or camera1's resolution otherwise, whatever the cameras
+    /// capture at
+    #[clap(long, requires = "output_height", value_parser = clap::value_parser!(u32).range(1..))]
+    output_width: Option<u32>,
+    /// Height of the frames written to --output-v4l2, --output-pipe and --once, like
+    /// --output-width
+    #[clap(long, requires = "output_width", value_parser = clap::value_parser!(u32).range(1..))]
+    output_height: Option<u32>,
+
+    /// Record both cameras' frames side by side to this video file, losslessly with FFV1 (needs
+    /// `ffmpeg`), and their capture times to the same path plus .csv, for depth processing
+    #[clap(long, value_name = "FILE", conflicts_with_all = ["cpu_render", "once", "headless"])]
+    record_stereo: Option<PathBuf>,
+
+    /// On exit, write capture and render timing statistics to this file as JSON
+    #[clap(long)]
+    metrics_json: Option<PathBuf>,
+
+    /// Use a transparent window in which black shows whatever is behind it
+    #[clap(long)]
+    transparent: bool,
+
+    /// Blend in linear light, by drawing into an sRGB framebuffer if the driver has one, so
+    /// the eyes mix without darkening in overlay and multiview mode
+    #[clap(long)]
+    linear_blend: bool,
+
+    /// GL internal format of the camera textures; raw Bayer frames always use a single 8-bit
+    /// channel. sRGB textures aren't needed for correct blending, --linear-blend decodes sRGB
+    /// in the shaders
+    #[clap(long, value_enum, default_value_t = TextureFormat::Auto)]
+    texture_format: TextureFormat,
 
-fn cam(path: &str, width: u32, height: u32) -> anyhow::Result<(ImageBuffer, Format)> {
-    println!("Using device: {}\n", path);
+    /// Log OpenGL errors after every texture upload and draw, and all driver debug output
+    #[clap(long)]
+    gl_debug: bool,
 
-    let buffer_count = 2;
+    /// OpenGL context profile to request, for drivers whose default is missing features or slow
+    #[clap(long, value_enum)]
+    gl_profile: Option<display::GlProfile>,
 
-    let format: Format;
-    let params: Parameters;
+    /// OpenGL version to request as MAJOR.MINOR, falling back to the default if unavailable
+    #[clap(long, value_parser = parse_gl_version)]
+    gl_version: Option<(u8, u8)>,
 
-    let dev = RwLock::new(Device::with_path(path)?);
-    {
-        let dev = dev.write().unwrap();
+    /// Keep showing the first frame of this camera while the other one stays live; toggle at
+    /// runtime with F
+    #[clap(
+        long,
+        value_parser = clap::value_parser!(u8).range(1..=2),
+        conflicts_with = "cpu_render"
+    )]
+    freeze_eye: Option<u8>,
 
-        dev.set_format(&Format::new(width, height, FourCC::new(b"MJPG")))
-            .context("Couldn't set format")?;
+    /// Composite the anaglyph on the CPU instead of with OpenGL shaders
+    #[clap(long)]
+    cpu_render: bool,
 
-        format = dev.format()?;
-        params = dev.params()?;
+    /// Convert MJPEG frames from YCbCr to RGB in the fragment shader instead of on the CPU;
+    /// entropy decoding and the IDCT still run in the capture threads
+    #[clap(long, conflicts_with = "cpu_render")]
+    gpu_color_convert: bool,
+}
 
-        println!("Active format:\n{}", format);
-        println!("Active parameters:\n{}", params);
+impl Cli {
+    /// The inputs of camera1 and camera2.
+    fn inputs(&self) -> [Input<'_>; 2] {
+        let input = |path, socket: Option<_>, file: Option<_>| match (socket, file) {
+            (Some(socket), _) => Input::Socket(socket),
+            (_, Some(file)) => Input::File(file),
+            _ => Input::Device(path),
+        };
+        [
+            input(
+                &self.camera1,
+                self.camera1_socket.as_deref(),
+                self.camera1_file.as_deref(),
+            ),
+            input(
+                &self.camera2,
+                self.camera2_socket.as_deref(),
+                self.camera2_file.as_deref(),
+            ),
+        ]
     }
 
-    let buffer = Arc::new(RwLock::new(Vec::new()));
+    /// The size given with --output-width and --output-height.
+    fn output_size(&self) -> Option<(u32, u32)> {
+        self.output_width.zip(self.output_height)
+    }
+}
 
-    thread::spawn({
-        let buffer = Arc::clone(&buffer);
-        move || {
-            let dev = dev.write().unwrap();
-            let mut stream =
-                MmapStream::with_buffers(&dev, Type::VideoCapture, buffer_count).unwrap();
+fn main() -> anyhow::Result<()> {
+    let matches = with_env_fallback(Cli::command()).get_matches();
+    let mut args = Cli::from_arg_matches(&matches)?;
+    args.flip_x |= args.selfie;
+    // before anything else is printed, since writing to stdout moves messages to stderr
+    let pipe = match &args.output_pipe {
+        Some(path) => Some(pipe::Pipe::open(
+            path,
+            args.output_pipe_format,
+            args.output_quality,
+        )?),
+        None => None,
+    };
+    if args.output_quality.is_some() && args.output_pipe_format != pipe::PipeFormat::Mjpeg {
+        eprintln!("WARNING: --output-quality only applies to --output-pipe-format mjpeg");
+    }
+    if let Some(path) = args.load_calibration.clone() {
+        calibration::load(&path, &mut args, &matches)?;
+    }
+    if let Some(path) = &args.probe {
+        return camera::print_capabilities(&camera::resolve(path)?);
+    }
+    if let Some(threads) = args.decode_threads {
+        camera::set_decode_threads(threads as usize)?;
+    }
+    if let Some(path) = &args.benchmark_decode {
+        return camera::benchmark_decode(path, args.benchmark_iterations, args.gpu_color_convert);
+    }
 
-            loop {
-                let (buf, _) = stream.next().unwrap();
-                let data = match &format.fourcc.repr {
-                    b"RGB3" => buf.to_vec(),
-                    b"MJPG" => {
-                        let mut decoder = jpeg::Decoder::new(buf);
-                        match decoder.decode() {
-                            Ok(data) => data,
-                            Err(error) => {
-                                eprintln!("failed to decode JPEG: {error}");
-                                continue;
-                            }
-                        }
-                    }
-                    _ => {
-                        eprintln!("invalid buffer pixelformat");
-                        continue;
-                    }
-                };
-                *buffer.write().unwrap() = data;
+    if args.dump_shaders {
+        for (camera, bayer) in [args.camera1_bayer, args.camera2_bayer]
+            .into_iter()
+            .enumerate()
+        {
+            let fragment = shaders::fragment_shader(&camera_shader_opts(&args, camera, bayer));
+            println!(
+                "// camera{} vertex shader{}",
+                camera + 1,
+                shaders::CAMERA_VERTEX
+            );
+            println!("// camera{} fragment shader{fragment}", camera + 1);
+        }
+        return Ok(());
+    }
+
+    let stills = match (&args.mpo, &args.sbs) {
+        (Some(path), _) => Some(stereo_image::load_mpo(path)?),
+        (_, Some(path)) => Some(stereo_image::load_sbs(path)?),
+        _ => None,
+    };
+    let live = stills.is_none();
+    if live {
+        for device in [&mut args.camera1, &mut args.camera2] {
+            *device = camera::resolve(device)?;
+        }
+    }
+    if let Some(base) = args.stereo_camera.as_deref() {
+        [args.camera1, args.camera2] = camera::stereo_pair(&camera::resolve(base)?)?;
+        println!(
+            "Stereo camera {base}: camera1 {}, camera2 {}",
+            args.camera1, args.camera2
+        );
+    } else if live && args.pick {
+        pick_cameras(&mut args)?;
+    } else if live {
+        pick_default_cameras(&mut args, &matches)?;
+    }
+    if args.dry_run {
+        let inputs = args.inputs().into_iter().enumerate().filter(|_| live);
+        for (index, input) in inputs {
+            // sockets and files only fail once frames are expected from them
+            if let Input::Device(path) = input {
+                camera::probe(path, capture_options(index, &args))?;
+            }
+        }
+        println!("Dry run succeeded");
+        return Ok(());
+    }
+    // before the cameras start streaming, which some drivers only allow changing the mode of
+    let triggers = match args.inputs() {
+        [Input::Device(path1), Input::Device(path2)] if live && args.trigger_mode => {
+            match (
+                camera::Trigger::enable(path1),
+                camera::Trigger::enable(path2),
+            ) {
+                (Ok(trigger1), Ok(trigger2)) => {
+                    println!("Triggering both cameras every frame");
+                    vec![trigger1, trigger2]
+                }
+                (Err(error), _) | (_, Err(error)) => {
+                    eprintln!("WARNING: {error:#}, capturing free-running");
+                    Vec::new()
+                }
+            }
+        }
+        _ if args.trigger_mode => {
+            eprintln!("WARNING: only two V4L cameras can be triggered, capturing free-running");
+            Vec::new()
+        }
+        _ => Vec::new(),
+    };
+    let focus_regions = args.inputs().map(|input| match input {
+        Input::Device(path) if live && args.focus_on_click => camera::FocusRegion::enable(path)
+            .inspect_err(|error| eprintln!("WARNING: {error:#}, clicking won't focus it"))
+            .ok(),
+        _ => None,
+    });
+    let (mut camera1, mut camera2) = match stills {
+        Some([left, right]) => (
+            Camera::still(left.data, left.width, left.height),
+            Camera::still(right.data, right.width, right.height),
+        ),
+        None => {
+            let camera1 = open_camera(0, args.inputs()[0], &args)?;
+            if args.stereo_camera.is_some() && !camera1.wait_for_frame(Duration::from_secs(2)) {
+                eprintln!("WARNING: camera1 hasn't delivered a frame, starting camera2 anyway");
             }
+            (camera1, open_camera(1, args.inputs()[1], &args)?)
         }
+    };
+    let mut watchdogs: [Option<camera::Watchdog>; 2] = std::array::from_fn(|index| {
+        let timeout = Duration::from_millis(args.stall_timeout_ms.filter(|_| live)?);
+        // restarting doesn't help a paused socket producer, and files restart themselves
+        let Input::Device(path) = args.inputs()[index] else {
+            return None;
+        };
+        Some(camera::Watchdog::new(
+            path,
+            capture_options(index, &args),
+            timeout,
+        ))
     });
 
-    Ok((buffer, format))
+    if let (Some(model1), Some(model2)) = (&camera1.model, &camera2.model) {
+        if model1 != model2 {
+            eprintln!(
+                "WARNING: camera1 is a {model1} but camera2 a {model2}; different models usually \
+                 differ in field of view, which distorts the depth"
+            );
+        }
+    }
+
+    if args.pin_threads {
+        match core_affinity::get_core_ids() {
+            None => eprintln!("WARNING: thread affinity isn't supported here, not pinning threads"),
+            Some(cores) if cores.len() < 3 => eprintln!(
+                "WARNING: only {} cores available, some threads won't be pinned",
+                cores.len()
+            ),
+            Some(_) => (),
+        }
+        if let Some(core) = thread_core(2, &args) {
+            if !core_affinity::set_for_current(core) {
+                eprintln!(
+                    "WARNING: couldn't pin the render thread to core {}",
+                    core.id
+                );
+            }
+        }
+    }
+
+    anyhow::ensure!(
+        args.clahe.is_none() || (camera1.bayer.is_none() && camera2.bayer.is_none()),
+        "--clahe can't equalize raw Bayer frames"
+    );
+    let mut render_ms = metrics::Reservoir::default();
+    let offscreen = if args.once {
+        Some(("--once", ONCE_OPTIONS))
+    } else if args.headless {
+        Some(("--headless", HEADLESS_OPTIONS))
+    } else {
+        None
+    };
+    if let Some((mode, options)) = offscreen {
+        let ignored = ignored_options(&matches, &[SETUP_OPTIONS, COMPOSITOR_OPTIONS, options]);
+        if !ignored.is_empty() {
+            eprintln!("WARNING: {mode} ignores {}", ignored.join(", "));
+        }
+    }
+    if let Some(path) = args.output.as_deref().filter(|_| args.once) {
+        let cameras = [&mut camera1, &mut camera2];
+        let result = snapshot::run(&args, cameras, !live, &triggers, path, &mut render_ms);
+        write_metrics(&args, [&camera1.stats, &camera2.stats], &render_ms);
+        return result;
+    }
+    if args.headless {
+        let sinks = output_sinks(&args, pipe)?;
+        let cameras = [&mut camera1, &mut camera2];
+        let result = snapshot::stream(&args, cameras, !live, &triggers, sinks, &mut render_ms);
+        write_metrics(&args, [&camera1.stats, &camera2.stats], &render_ms);
+        return result;
+    }
+
+    let [mask1, mask2] = eye_masks(&args, args.color_scheme);
+    if args.cpu_render {
+        anyhow::ensure!(
+            camera1.bayer.is_none() && camera2.bayer.is_none(),
+            "--cpu-render can't demosaic raw Bayer frames"
+        );
+        let ignored = ignored_options(&matches, &[SETUP_OPTIONS, CPU_RENDER_OPTIONS]);
+        if !ignored.is_empty() {
+            eprintln!("WARNING: --cpu-render ignores {}", ignored.join(", "));
+        }
+        let result = cpu::run(
+            &window_title(&args, Mode::Anaglyph, args.color_scheme),
+            [
+                (
+                    camera1.buffer,
+                    cpu::EyeOptions {
+                        flip_x: args.flip_x,
+                        flip_y: args.camera1_flip_y,
+                    },
+                    mask1,
+                ),
+                (
+                    camera2.buffer,
+                    cpu::EyeOptions {
+                        flip_x: args.flip_x,
+                        flip_y: args.camera2_flip_y,
+                    },
+                    mask2,
+                ),
+            ],
+            &triggers,
+            &mut render_ms,
+        );
+        write_metrics(&args, [&camera1.stats, &camera2.stats], &render_ms);
+        return result;
+    }
+
+    let event_loop = winit::event_loop::EventLoop::new()?;
+    let (window, display, gl_errors) = display::build(
+        &event_loop,
+        &window_title(&args, args.mode, args.color_scheme),
+        display::DisplayOptions {
+            transparent: args.transparent,
+            gl_debug: args.gl_debug,
+            gl_profile: args.gl_profile,
+            gl_version: args.gl_version,
+            srgb: args.linear_blend,
+        },
+    )?;
+    let mut window2 = args
+        .window2_mode
+        .map(|mode| {
+            let title = format!("{} (window2)", window_title(&args, mode, args.color_scheme));
+            second_window::SecondWindow::open(&event_loop, &title, mode)
+        })
+        .transpose()?;
+    window.request_redraw();
+    window.set_fullscreen(Some(winit::window::Fullscreen::Borderless(None)));
+    window.focus_window();
+    if let Some(&[x, y, w, h]) = args.viewport.as_deref() {
+        // the window is fullscreen, but may not have been resized yet
+        let size = window
+            .current_monitor()
+            .map_or(window.inner_size(), |monitor| monitor.size());
+        anyhow::ensure!(
+            w > 0 && h > 0 && x + w <= size.width && y + h <= size.height,
+            "--viewport {x},{y},{w},{h} doesn't fit into the {}x{} window",
+            size.width,
+            size.height
+        );
+    }
+    event_loop.set_control_flow(match args.power_mode {
+        PowerMode::Performance => winit::event_loop::ControlFlow::Poll,
+        // wait until when the next frame is due, set after every frame
+        PowerMode::Balanced | PowerMode::PowerSave => winit::event_loop::ControlFlow::Wait,
+    });
+    let refresh_interval = window
+        .current_monitor()
+        .and_then(|monitor| monitor.refresh_rate_millihertz())
+        .map_or(Duration::from_secs(1) / 60, |millihertz| {
+            Duration::from_secs(1000) / millihertz
+        });
+    if args.power_mode == PowerMode::PowerSave {
+        for camera in [&camera1, &camera2] {
+            let proxy = event_loop.create_proxy();
+            camera.on_frame(move || {
+                let _ = proxy.send_event(());
+            });
+        }
+    }
+
+    let (vertex_buffer, index_buffer) = quad(&display);
+
+    args.texture_format.check(&display)?;
+    let linear = args.linear_blend && glium::CapabilitiesSource::get_capabilities(&display).srgb;
+    if args.linear_blend && !linear {
+        eprintln!("WARNING: no sRGB framebuffer available, blending sRGB values");
+    }
+    let opts = |camera, bayer| shaders::ShaderOpts {
+        linear,
+        ..camera_shader_opts(&args, camera, bayer)
+    };
+    let program_camera1 = build_camera_program(&display, &opts(0, camera1.bayer))?;
+    let program_camera2 = build_camera_program(&display, &opts(1, camera2.bayer))?;
+
+    // combines both eyes after they've been rendered in full color, for effects that need to
+    // see the other eye's pixel; with --linear-blend, they're rendered in linear light
+    let program_combine = shaders::compile_with_output(
+        &display,
+        "combine",
+        shaders::QUAD_VERTEX,
+        &format!(
+            "
+                #version 140
+                uniform sampler2D camera1;
+                uniform sampler2D camera2;
+                uniform vec3 mask1;
+                uniform vec3 mask2;
+                uniform float crosstalk;
+                uniform float comfort;
+                uniform bool dubois;
+                uniform mat3 dubois1;
+                uniform mat3 dubois2;
+                in vec2 v_tex_coords;
+                out vec4 f_color;
+
+                void main() {{
+                    vec3 eye1 = texture(camera1, v_tex_coords).rgb;
+                    vec3 eye2 = texture(camera2, v_tex_coords).rgb;
+                    // give each eye some of the other's luminance, both get the average at 1
+                    vec3 luma = vec3(0.299, 0.587, 0.114);
+                    vec3 mixed1 = mix(eye1, vec3(dot(eye2, luma)), comfort / 2.0);
+                    vec3 mixed2 = mix(eye2, vec3(dot(eye1, luma)), comfort / 2.0);
+                    eye1 = mixed1;
+                    eye2 = mixed2;
+                    if (dubois) {{
+                        f_color.rgb = clamp(dubois1 * eye1 + dubois2 * eye2, 0.0, 1.0);
+                    }} else {{
+                        // take out what of the other eye will leak through each lens
+                        f_color.rgb = mask1 * (eye1 - crosstalk * eye2)
+                            + mask2 * (eye2 - crosstalk * eye1);
+                    }}
+                    f_color.a = 1.0;
+                    {alpha}
+                }}
+            ",
+            alpha = if args.transparent {
+                shaders::BRIGHTNESS_ALPHA
+            } else {
+                ""
+            }
+        ),
+        linear,
+    )?;
+
+    let program_clahe = args.clahe.map(|_| clahe::program(&display)).transpose()?;
+
+    // mixes a camera's new frame with its previous one for --denoise and --interpolate, and
+    // with the mean of the ones before it for --frame-average
+    let program_mix = shaders::compile(
+        &display,
+        "mix",
+        shaders::QUAD_VERTEX,
+        "
+                #version 140
+                uniform sampler2D current;
+                uniform sampler2D previous;
+                uniform float strength;
+                in vec2 v_tex_coords;
+                out vec4 f_color;
+
+                void main() {
+                    f_color = mix(
+                        texture(current, v_tex_coords),
+                        texture(previous, v_tex_coords),
+                        strength
+                    );
+                }
+            ",
+    )?;
+
+    // --simulate-cvd, applied to a copy of the composited image
+    let program_cvd = shaders::compile(
+        &display,
+        "color vision",
+        shaders::QUAD_VERTEX,
+        "
+                #version 140
+                uniform sampler2D image;
+                uniform mat3 cvd;
+                in vec2 v_tex_coords;
+                out vec4 f_color;
+
+                void main() {
+                    f_color = texture(image, v_tex_coords);
+                    vec3 linear = pow(f_color.rgb, vec3(2.2));
+                    f_color.rgb = pow(clamp(cvd * linear, 0.0, 1.0), vec3(1.0 / 2.2));
+                }
+            ",
+    )?;
+
+    // scrolling color bars for --no-signal-pattern
+    let program_no_signal = shaders::compile(
+        &display,
+        "no signal",
+        shaders::QUAD_VERTEX,
+        "
+                #version 140
+                uniform float time;
+                in vec2 v_tex_coords;
+                out vec4 f_color;
+
+                void main() {
+                    // white, yellow, cyan, green, magenta, red, blue, black
+                    int bar = int(floor(fract(v_tex_coords.x + time * 0.1) * 8.0));
+                    f_color = vec4(
+                        float(bar == 0 || bar == 1 || bar == 4 || bar == 5),
+                        float(bar <= 3),
+                        float(bar == 0 || bar == 2 || bar == 4 || bar == 6),
+                        1.0
+                    ) * 0.75;
+                }
+            ",
+    )?;
+
+    let mut settings = settings::Settings::new(&args);
+    let mut panel = panel::Panel::new(&display, &window, &event_loop);
+    panel.self_test = args.self_test;
+    panel.verify_glasses = args.verify_glasses.then(Vec::new);
+    // toggled by --auto-eye-order when the cameras are the wrong way round, and by
+    // --verify-glasses when the glasses are
+    let mut eyes_swapped =
+        args.auto_eye_order && cameras_swapped(&args, &mut camera1, &mut camera2, live);
+    // smoothed over the last frames, for the HUD
+    let mut fps = 0.0f32;
+    let mut last_render = Instant::now();
+    let mut graph = graph::Graph::new(&display, args.graph)?;
+    let mut mode = args.mode;
+    let mut freeze_eye = args.freeze_eye;
+    let mut cursor = None;
+    // the camera shown on its own by --fallback-2d, 0 or 1
+    let mut alone = None;
+    // for --max-latency-ms, how many frames each camera had too old, and the last one counted
+    let mut stale_frames = [0u32; 2];
+    let mut last_stale: [Option<Instant>; 2] = [None, None];
+    // brightness scales from --auto-match
+    let mut exposure = [1.0f32; 2];
+    if let Some(eye) = freeze_eye {
+        println!("Freezing camera{eye}");
+    }
+    let mut textures: [Option<glium::texture::Texture2d>; 2] = [None, None];
+    // for --interpolate, the frame before the one in `textures`, and when and how long after
+    // it that one arrived
+    let mut previous: [Option<glium::texture::Texture2d>; 2] = [None, None];
+    let mut arrivals: [(Instant, Duration); 2] = [(Instant::now(), Duration::ZERO); 2];
+    // each camera's last frames for --frame-average, oldest first
+    let frame_average = [args.camera1_frame_average, args.camera2_frame_average]
+        .map(|frames| frames.or(args.frame_average).unwrap_or(1) as usize);
+    let mut history: [VecDeque<glium::texture::Texture2d>; 2] = Default::default();
+    // capture times of the frames in `textures`, and of the ones whose latency was recorded
+    let mut shown: [Option<Instant>; 2] = [None, None];
+    let mut measured: [Option<Instant>; 2] = [None, None];
+    let mut latency = args.print_latency.then(metrics::Latency::new);
+    let mut sync_gpio = args
+        .sync_gpio
+        .and_then(|pin| sync_gpio::SyncGpio::open(&args.sync_gpio_chip, pin));
+    // capture times of the pair the sync GPIO last flipped for
+    let mut synced: [Option<Instant>; 2] = [None, None];
+    let mut sinks = output_sinks(&args, pipe)?;
+    let mut recorder = match &args.record_stereo {
+        Some(path) => {
+            anyhow::ensure!(
+                camera1.bayer.is_none() && camera2.bayer.is_none(),
+                "--record-stereo can't record raw Bayer frames"
+            );
+            Some(recording::StereoRecorder::start(path)?)
+        }
+        None => None,
+    };
+    // what the sinks are sent instead of the viewport's contents when the sizes differ
+    let scaled_output = args
+        .output_size()
+        .filter(|_| !sinks.is_empty())
+        .map(|(width, height)| glium::texture::Texture2d::empty(&display, width, height))
+        .transpose()?;
+    let blend = if args.transparent {
+        glium::Blend {
+            color: glium::BlendingFunction::Addition {
+                source: glium::LinearBlendingFactor::One,
+                destination: glium::LinearBlendingFactor::OneMinusSourceAlpha,
+            },
+            alpha: glium::BlendingFunction::Addition {
+                source: glium::LinearBlendingFactor::One,
+                destination: glium::LinearBlendingFactor::OneMinusSourceAlpha,
+            },
+            constant_value: (0.0, 0.0, 0.0, 0.0),
+        }
+    } else {
+        glium::Blend::alpha_blending()
+    };
+
+    let mut composited: Option<Composited> = None;
+    let started = Instant::now();
+    event_loop.run(move |event, elwt| {
+        // with --power-mode balanced or power-save, one frame per wakeup rather than per event
+        let render = args.power_mode == PowerMode::Performance
+            || matches!(event, winit::event::Event::AboutToWait);
+
+        if let (winit::event::Event::LoopExiting, Some(recorder)) = (&event, recorder.take()) {
+            recorder.finish();
+        }
+        if let (winit::event::Event::LoopExiting, Some(max)) = (&event, args.max_latency_ms) {
+            for (camera, stale) in [&args.camera1, &args.camera2].iter().zip(stale_frames) {
+                println!("\n{camera}: {stale} frames not shown for being older than {max} ms");
+            }
+        }
+        if let winit::event::Event::LoopExiting = event {
+            write_metrics(&args, [&camera1.stats, &camera2.stats], &render_ms);
+        }
+
+        if let winit::event::Event::WindowEvent { event, window_id } = event {
+            if let winit::event::WindowEvent::KeyboardInput {
+                event:
+                    winit::event::KeyEvent {
+                        state: winit::event::ElementState::Pressed,
+                        physical_key,
+                        ..
+                    },
+                ..
+            } = event
+            {
+                if let Some(answers) = &mut panel.verify_glasses {
+                    match physical_key {
+                        winit::keyboard::PhysicalKey::Code(KeyCode::KeyL) => {
+                            answers.push(panel::Lens::Left)
+                        }
+                        winit::keyboard::PhysicalKey::Code(KeyCode::KeyR) => {
+                            answers.push(panel::Lens::Right)
+                        }
+                        _ => return,
+                    }
+                    if let [left, right] = answers[..] {
+                        use panel::Lens::{Left, Right};
+                        match (left, right) {
+                            (Left, Right) => println!("\nThe glasses are on the right way round"),
+                            (Right, Left) => {
+                                println!("\nThe glasses are on the other way round, swapping eyes");
+                                eyes_swapped = !eyes_swapped;
+                            }
+                            _ => eprintln!(
+                                "\nWARNING: both colors seen with the same eye, are the glasses \
+                                 for {}?",
+                                settings.color_scheme
+                            ),
+                        }
+                        panel.verify_glasses = None;
+                    }
+                    return;
+                }
+                if panel.self_test {
+                    panel.self_test = false;
+                    return;
+                }
+            }
+            if let winit::event::WindowEvent::KeyboardInput {
+                event:
+                    winit::event::KeyEvent {
+                        physical_key: winit::keyboard::PhysicalKey::Code(KeyCode::Tab),
+                        state: winit::event::ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } = event
+            {
+                panel.visible = !panel.visible;
+                return;
+            }
+            // window2 has no panel; keys pressed in it act like in the main window
+            if window_id == window.id() && panel.on_event(&window, &event) {
+                return;
+            }
+            match event {
+                winit::event::WindowEvent::CloseRequested => elwt.exit(),
+                winit::event::WindowEvent::Resized(size) if window_id == window.id() => {
+                    display.resize(size.into())
+                }
+                winit::event::WindowEvent::KeyboardInput {
+                    event:
+                        winit::event::KeyEvent {
+                            physical_key: winit::keyboard::PhysicalKey::Code(key),
+                            state: winit::event::ElementState::Pressed,
+                            ..
+                        },
+                    ..
+                } => {
+                    let [x, y] = &mut settings.camera2_offset;
+                    match key {
+                        KeyCode::ArrowLeft if mode == Mode::Overlay => *x -= 1,
+                        KeyCode::ArrowRight if mode == Mode::Overlay => *x += 1,
+                        KeyCode::ArrowUp if mode == Mode::Overlay => *y -= 1,
+                        KeyCode::ArrowDown if mode == Mode::Overlay => *y += 1,
+                        KeyCode::KeyJ => *x -= 1,
+                        KeyCode::KeyL => *x += 1,
+                        KeyCode::KeyI => *y -= 1,
+                        KeyCode::KeyK => *y += 1,
+                        KeyCode::ArrowLeft => settings.convergence -= 1.0,
+                        KeyCode::ArrowRight => settings.convergence += 1.0,
+                        KeyCode::ArrowUp => settings.depth_bias += 1.0,
+                        KeyCode::ArrowDown => settings.depth_bias -= 1.0,
+                        KeyCode::BracketLeft => {
+                            settings.crosstalk = (settings.crosstalk - 0.01).max(0.0)
+                        }
+                        KeyCode::BracketRight => {
+                            settings.crosstalk = (settings.crosstalk + 0.01).min(1.0)
+                        }
+                        KeyCode::Digit1 | KeyCode::Digit2 | KeyCode::Digit9 | KeyCode::Digit0 => {
+                            let (eye, step) = match key {
+                                KeyCode::Digit1 => (0, -0.05),
+                                KeyCode::Digit2 => (0, 0.05),
+                                KeyCode::Digit9 => (1, -0.05),
+                                _ => (1, 0.05),
+                            };
+                            let gain = &mut settings.eye_gains[eye];
+                            *gain = (*gain + step).max(0.0);
+                            println!("\n{}", settings.eye_gain_flags());
+                        }
+                        KeyCode::Comma => settings.comfort = (settings.comfort - 0.05).max(0.0),
+                        KeyCode::Period => settings.comfort = (settings.comfort + 0.05).min(1.0),
+                        KeyCode::KeyC => {
+                            settings.color_scheme = settings.color_scheme.next();
+                            let title = window_title(&args, mode, settings.color_scheme);
+                            window.set_title(&title);
+                        }
+                        KeyCode::KeyD => {
+                            mode = match (mode, args.mode) {
+                                (Mode::Disparity, Mode::Disparity) => Mode::Anaglyph,
+                                (Mode::Disparity, mode) => mode,
+                                _ => Mode::Disparity,
+                            };
+                            window.set_title(&window_title(&args, mode, settings.color_scheme));
+                        }
+                        KeyCode::KeyF => {
+                            freeze_eye = match freeze_eye {
+                                None => Some(1),
+                                Some(1) => Some(2),
+                                _ => None,
+                            };
+                            match freeze_eye {
+                                Some(eye) => println!("\nFreezing camera{eye}"),
+                                None => println!("\nBoth cameras live"),
+                            }
+                        }
+                        KeyCode::KeyW => {
+                            let means =
+                                [&mut camera1, &mut camera2].map(|camera| rgb_means(camera, 16));
+                            [settings.camera1_gains, settings.camera2_gains] =
+                                color::gray_world_gains(means);
+                            println!("\n{}", settings.gain_flags());
+                        }
+                        KeyCode::KeyH => panel.hud = !panel.hud,
+                        KeyCode::KeyG => graph.visible = !graph.visible,
+                        // F already freezes a camera
+                        KeyCode::F11 if window_id == window.id() => {
+                            window.set_fullscreen(match window.fullscreen() {
+                                Some(_) => None,
+                                None => Some(winit::window::Fullscreen::Borderless(
+                                    window.current_monitor(),
+                                )),
+                            })
+                        }
+                        KeyCode::KeyP => println!("\n{}", settings.flags()),
+                        KeyCode::KeyS => match &args.save_calibration {
+                            Some(path) => match calibration::save(path, &settings, &args) {
+                                Ok(()) => println!("\nSaved calibration to {}", path.display()),
+                                Err(error) => eprintln!("\n{error:#}"),
+                            },
+                            None => println!("\nGive --save-calibration to save with S"),
+                        },
+                        KeyCode::KeyR => {
+                            let resolutions =
+                                [&camera1, &camera2].map(|camera| &camera.resolutions[..]);
+                            match next_resolution(camera1.size(), resolutions) {
+                                Some((width, height)) => {
+                                    println!("\nSwitching to {width}x{height}");
+                                    camera1.set_resolution(width, height);
+                                    camera2.set_resolution(width, height);
+                                }
+                                // neither says what it supports, e.g. still images
+                                None if resolutions.iter().all(|sizes| sizes.is_empty()) => (),
+                                None => println!("\nThe cameras have no resolution in common"),
+                            }
+                        }
+                        _ => (),
+                    }
+                    let (width, height) = camera2.size();
+                    settings.clamp_offset(width, height);
+                }
+                winit::event::WindowEvent::CursorMoved { position, .. } => cursor = Some(position),
+                winit::event::WindowEvent::MouseInput {
+                    state: winit::event::ElementState::Pressed,
+                    button: winit::event::MouseButton::Left,
+                    ..
+                } => {
+                    let Some(position) = cursor else {
+                        return;
+                    };
+                    let size = window.inner_size();
+                    let point = [
+                        position.x as f32 / size.width as f32,
+                        position.y as f32 / size.height as f32,
+                    ];
+                    let cameras = [
+                        (&camera1, args.camera1_flip_y, args.camera1_scale),
+                        (&camera2, args.camera2_flip_y, args.camera2_scale),
+                    ];
+                    for ((camera, flip_y, magnification), focus) in
+                        cameras.into_iter().zip(&focus_regions)
+                    {
+                        let Some(focus) = focus else {
+                            continue;
+                        };
+                        let scale = args
+                            .fit
+                            .scale(camera.size(), (size.width, size.height))
+                            .map(|scale| scale * magnification);
+                        let [x, y] = window_to_image(point, scale, args.flip_x, flip_y);
+                        if let Err(error) = focus.focus_on(x, y) {
+                            eprintln!("\ncouldn't set the autofocus region: {error}");
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if !render {
+            return;
+        }
+        let t0 = Instant::now();
+        let [mask1, mask2] = match eye_masks(&args, settings.color_scheme) {
+            [mask1, mask2] if eyes_swapped => [mask2, mask1],
+            masks => masks,
+        };
+
+        camera::fire(&triggers);
+
+        for (camera, watchdog) in [&mut camera1, &mut camera2].into_iter().zip(&mut watchdogs) {
+            if let Some(watchdog) = watchdog {
+                watchdog.check(camera);
+            }
+        }
+
+        if let Some(smoothing) = args.auto_match {
+            let luminance =
+                [&mut camera1, &mut camera2].map(|camera| color::luminance(rgb_means(camera, 64)));
+            let brightest = luminance[0].max(luminance[1]);
+            if luminance.iter().all(|&l| l > 0.0) {
+                for (exposure, luminance) in exposure.iter_mut().zip(luminance) {
+                    let target = brightest / luminance;
+                    *exposure = smoothing * *exposure + (1.0 - smoothing) * target;
+                }
+            }
+        }
+
+        let check_gl = |stage: &str| {
+            if let Some(gl_errors) = &gl_errors {
+                gl_errors.check(stage);
+            }
+        };
+
+        let window_target = display.draw();
+        let size = window_target.get_dimensions();
+        // a minimized window is 0x0, which textures can't be
+        let texture_size = (size.0.max(1), size.1.max(1));
+        if composited.as_ref().map(Composited::dimensions) != Some(texture_size) {
+            composited = Some(Composited::new(&display, linear, texture_size).unwrap());
+        }
+        // the anaglyph is drawn offscreen so the sinks get it without what's drawn over it
+        let mut target = composited.as_ref().unwrap().framebuffer(&display);
+        let area = viewport(args.viewport.as_deref(), size);
+        target.clear(Some(&area), Some((0.0, 0.0, 0.0, 0.0)), false, None, None);
+
+        let upload = |camera: &mut Camera| {
+            let frame = camera.frame();
+            let texture = frame_texture(&display, frame, args.texture_format);
+            check_gl("texture upload");
+            match (texture, &program_clahe, args.clahe) {
+                (Some(texture), Some(program), Some(clip_limit)) => {
+                    let gain_map = clahe::gain_map(frame, clip_limit);
+                    let quad = (&vertex_buffer, &index_buffer);
+                    let ycbcr = frame.pixel_format == PixelFormat::Ycbcr;
+                    let equalized =
+                        clahe::apply(&display, program, quad, &texture, gain_map, ycbcr).unwrap();
+                    check_gl("clahe");
+                    Some(equalized)
+                }
+                (texture, ..) => texture,
+            }
+        };
+        let newest = [&camera1, &camera2].map(|camera| *camera.captured.lock().unwrap());
+        let take = args.sync_strategy.take(shown, newest);
+        let cameras = [(1, &mut camera1), (2, &mut camera2)];
+        for ((((eye, camera), texture), shown), (previous, arrival)) in cameras
+            .into_iter()
+            .zip(&mut textures)
+            .zip(&mut shown)
+            .zip(previous.iter_mut().zip(&mut arrivals))
+        {
+            // a frozen eye keeps its texture, once it has one
+            if (freeze_eye != Some(eye) && take[usize::from(eye) - 1]) || texture.is_none() {
+                let captured = newest[usize::from(eye) - 1];
+                if args.interpolate && captured != *shown {
+                    *previous = texture.take();
+                    *arrival = (Instant::now(), arrival.0.elapsed());
+                }
+                let new = captured != *shown;
+                *shown = captured;
+                let mut uploaded = upload(camera);
+                let frames = frame_average[usize::from(eye) - 1];
+                if frames > 1 {
+                    let history = &mut history[usize::from(eye) - 1];
+                    if let Some(current) = uploaded.take().filter(|_| new) {
+                        // a resolution switch starts over
+                        history.retain(|frame| frame.dimensions() == current.dimensions());
+                        if history.len() == frames {
+                            history.pop_front();
+                        }
+                        history.push_back(current);
+                    }
+                    let quad = (&vertex_buffer, &index_buffer);
+                    uploaded = average(&display, quad, &program_mix, history);
+                    check_gl("frame average");
+                }
+                *texture = match (uploaded, texture.take(), args.denoise) {
+                    (Some(current), Some(previous), Some(strength))
+                        if current.dimensions() == previous.dimensions() =>
+                    {
+                        // blend into the previous result, so it averages over all past frames
+                        let (width, height) = current.dimensions();
+                        let denoised =
+                            glium::texture::Texture2d::empty(&display, width, height).unwrap();
+                        glium::framebuffer::SimpleFrameBuffer::new(&display, &denoised)
+                            .unwrap()
+                            .draw(
+                                &vertex_buffer,
+                                &index_buffer,
+                                &program_mix,
+                                &uniform! {
+                                    current: &current,
+                                    previous: &previous,
+                                    strength: strength,
+                                },
+                                &Default::default(),
+                            )
+                            .unwrap();
+                        check_gl("denoise");
+                        Some(denoised)
+                    }
+                    (uploaded, _, _) => uploaded,
+                };
+            }
+        }
+        if let Some(recorder) = &mut recorder {
+            recorder.offer(newest, || {
+                [&mut camera1, &mut camera2].map(|camera| camera.frame().clone())
+            });
+        }
+        let interpolated = [0, 1].map(|eye| {
+            let (Some(current), Some(previous)) = (&textures[eye], &previous[eye]) else {
+                return None;
+            };
+            let (arrived, interval) = arrivals[eye];
+            let progress = arrived.elapsed().as_secs_f32() / interval.as_secs_f32();
+            if interval.is_zero()
+                || progress >= 1.0
+                || current.dimensions() != previous.dimensions()
+            {
+                return None;
+            }
+            let (width, height) = current.dimensions();
+            let mixed = glium::texture::Texture2d::empty(&display, width, height).unwrap();
+            glium::framebuffer::SimpleFrameBuffer::new(&display, &mixed)
+                .unwrap()
+                .draw(
+                    &vertex_buffer,
+                    &index_buffer,
+                    &program_mix,
+                    &uniform! {
+                        current: current,
+                        previous: previous,
+                        strength: 1.0 - progress,
+                    },
+                    &Default::default(),
+                )
+                .unwrap();
+            check_gl("interpolation");
+            Some(mixed)
+        });
+        let fresh = [0, 1].map(|eye| {
+            let (Some(max), Some(captured)) = (args.max_latency_ms, shown[eye]) else {
+                return true;
+            };
+            if freeze_eye == Some(eye as u8 + 1) || captured.elapsed().as_millis() <= max.into() {
+                return true;
+            }
+            if last_stale[eye] != Some(captured) {
+                last_stale[eye] = Some(captured);
+                stale_frames[eye] += 1;
+            }
+            false
+        });
+        let [texture1, texture2] = [0, 1].map(|eye| {
+            let texture = interpolated[eye].as_ref().or(textures[eye].as_ref());
+            texture.filter(|_| fresh[eye])
+        });
+        let camera2_shift = [
+            settings.camera2_offset[0] as f32 + settings.depth_bias,
+            settings.camera2_offset[1] as f32,
+        ];
+        // what the cameras didn't already rotate themselves
+        let [roll1, roll2] = [(&camera1, args.camera1_roll), (&camera2, args.camera2_roll)]
+            .map(|(camera, roll)| if camera.rotated { 0.0 } else { roll });
+
+        let (width, height) = (area.width, area.height);
+        // --flip-output-y turns the quads over, so it applies after every per-camera transform
+        let upside_down = |eye: Eye| {
+            if args.flip_output_y {
+                Eye {
+                    scale: [eye.scale[0], -eye.scale[1]],
+                    ..eye
+                }
+            } else {
+                eye
+            }
+        };
+        let mut draw = |program: &glium::Program,
+                        texture: &glium::texture::Texture2d,
+                        eye: Eye,
+                        params: &glium::DrawParameters| {
+            let params = glium::DrawParameters {
+                viewport: params.viewport.or(Some(area)),
+                ..params.clone()
+            };
+            target
+                .draw(
+                    &vertex_buffer,
+                    &index_buffer,
+                    program,
+                    &camera_uniforms(texture, upside_down(eye), args.edge),
+                    &params,
+                )
+                .unwrap();
+        };
+
+        let [eye1, eye2] = eyes(
+            &args,
+            &settings,
+            [&camera1, &camera2],
+            exposure,
+            (width, height),
+        );
+
+        if let Some(timeout) = args
+            .fallback_2d
+            .filter(|_| live)
+            .map(Duration::from_secs_f32)
+        {
+            // a frozen eye keeps its last frame on purpose, so it counts as having signal
+            let signal = [(1, &camera1), (2, &camera2)].map(|(eye, camera)| {
+                freeze_eye == Some(eye)
+                    || camera
+                        .captured
+                        .lock()
+                        .unwrap()
+                        .is_some_and(|captured| captured.elapsed() <= timeout)
+            });
+            let now_alone = match signal {
+                [true, false] => Some(0),
+                [false, true] => Some(1),
+                // with neither, keep showing what was there until --no-signal-pattern kicks in
+                [false, false] => alone,
+                [true, true] => None,
+            };
+            if now_alone != alone {
+                match now_alone {
+                    Some(camera) => println!(
+                        "\ncamera{} has no signal, showing camera{} in 2D",
+                        2 - camera,
+                        camera + 1
+                    ),
+                    None => println!("\nBoth cameras have signal, back to the anaglyph"),
+                }
+                alone = now_alone;
+            }
+        }
+
+        match mode {
+            Mode::Anaglyph if alone.is_some() => {
+                let (program, texture, eye) = match alone {
+                    Some(0) => (&program_camera1, texture1, eye1),
+                    _ => (&program_camera2, texture2, eye2),
+                };
+                if let Some(texture) = texture {
+                    let params = glium::DrawParameters {
+                        blend,
+                        ..Default::default()
+                    };
+                    draw(program, texture, eye, &params);
+                    check_gl("2D fallback draw");
+                }
+            }
+            Mode::Anaglyph => match (texture1, texture2) {
+                (Some(texture1), Some(texture2))
+                    if settings.crosstalk > 0.0 || settings.comfort > 0.0 || args.dubois =>
+                {
+                    let render_eye = |program, texture, eye| {
+                        let rendered =
+                            glium::texture::Texture2d::empty(&display, width, height).unwrap();
+                        let mut framebuffer =
+                            glium::framebuffer::SimpleFrameBuffer::new(&display, &rendered)
+                                .unwrap();
+                        framebuffer
+                            .draw(
+                                &vertex_buffer,
+                                &index_buffer,
+                                program,
+                                &camera_uniforms(texture, upside_down(eye), args.edge),
+                                &Default::default(),
+                            )
+                            .unwrap();
+                        rendered
+                    };
+                    let eye1 = render_eye(&program_camera1, texture1, eye1);
+                    let eye2 = render_eye(&program_camera2, texture2, eye2);
+                    check_gl("eye render");
+                    // camera1 is seen through the right lens with --selfie or swapped glasses
+                    let [left, right] = settings.color_scheme.dubois();
+                    let [dubois1, dubois2] = if mask1 == settings.color_scheme.masks()[0] {
+                        [left, right]
+                    } else {
+                        [right, left]
+                    };
+                    let uniforms = uniform! {
+                        camera1: &eye1,
+                        camera2: &eye2,
+                        mask1: mask1.factors(),
+                        mask2: mask2.factors(),
+                        crosstalk: settings.crosstalk,
+                        comfort: settings.comfort,
+                        dubois: args.dubois,
+                        dubois1: dubois1,
+                        dubois2: dubois2,
+                    };
+                    let params = glium::DrawParameters {
+                        blend,
+                        viewport: Some(area),
+                        ..Default::default()
+                    };
+                    target
+                        .draw(
+                            &vertex_buffer,
+                            &index_buffer,
+                            &program_combine,
+                            &uniforms,
+                            &params,
+                        )
+                        .unwrap();
+                    check_gl("combine draw");
+                }
+                _ => {
+                    if let Some(texture) = texture1 {
+                        let params = glium::DrawParameters {
+                            blend,
+                            color_mask: mask1.color_mask(),
+                            ..Default::default()
+                        };
+                        draw(&program_camera1, texture, eye1, &params);
+                        check_gl("camera1 draw");
+                    }
+                    if let Some(texture) = texture2 {
+                        let params = glium::DrawParameters {
+                            blend,
+                            color_mask: mask2.color_mask(),
+                            ..Default::default()
+                        };
+                        draw(&program_camera2, texture, eye2, &params);
+                        check_gl("camera2 draw");
+                    }
+                }
+            },
+            Mode::Overlay => {
+                if let Some(texture) = texture1 {
+                    let params = glium::DrawParameters {
+                        blend,
+                        ..Default::default()
+                    };
+                    draw(&program_camera1, texture, eye1, &params);
+                }
+                if let Some(texture) = texture2 {
+                    let fade = glium::BlendingFunction::Addition {
+                        source: glium::LinearBlendingFactor::ConstantAlpha,
+                        destination: glium::LinearBlendingFactor::OneMinusConstantAlpha,
+                    };
+                    let params = glium::DrawParameters {
+                        blend: glium::Blend {
+                            color: fade,
+                            alpha: fade,
+                            constant_value: (0.0, 0.0, 0.0, args.overlay_opacity),
+                        },
+                        ..Default::default()
+                    };
+                    draw(&program_camera2, texture, eye2, &params);
+                }
+                check_gl("overlay draw");
+            }
+            Mode::SideBySide => {
+                let half = width / 2;
+                let [eye1, eye2] = eyes(
+                    &args,
+                    &settings,
+                    [&camera1, &camera2],
+                    exposure,
+                    (half, height),
+                );
+                let views = [
+                    (&program_camera1, texture1, eye1),
+                    (&program_camera2, texture2, eye2),
+                ];
+                for (index, (program, texture, eye)) in views.into_iter().enumerate() {
+                    if let Some(texture) = texture {
+                        let params = glium::DrawParameters {
+                            blend,
+                            viewport: Some(glium::Rect {
+                                left: area.left + index as u32 * half,
+                                bottom: area.bottom,
+                                width: half,
+                                height,
+                            }),
+                            ..Default::default()
+                        };
+                        draw(program, texture, eye, &params);
+                    }
+                }
+                check_gl("side by side draw");
+            }
+            Mode::Multiview => {
+                let views = args.views.max(2);
+                let columns = args.view_columns.unwrap_or(views).clamp(1, views);
+                let rows = views.div_ceil(columns);
+                let (tile_width, tile_height) = (width / columns, height / rows);
+                for view in 0..views {
+                    // position of the virtual camera, 0 at camera1 and 1 at camera2
+                    let t = view as f32 / (views - 1) as f32;
+                    let viewport = glium::Rect {
+                        left: area.left + view % columns * tile_width,
+                        // GL's origin is the bottom left, tiles are laid out from the top
+                        bottom: area.bottom + height - (view / columns + 1) * tile_height,
+                        width: tile_width,
+                        height: tile_height,
+                    };
+                    // shift both images toward the virtual camera, assuming the scene's
+                    // disparity is --convergence everywhere, and cross-fade between them
+                    if let Some(texture) = &texture1 {
+                        let eye = Eye {
+                            offset: [-t * settings.convergence, 0.0],
+                            roll: roll1,
+                            magnification: args.camera1_scale,
+                            gain: settings.camera1_gains,
+                            ycbcr: camera1.ycbcr,
+                            brightness: settings.brightness * exposure[0] * settings.eye_gains[0],
+                            gamma: settings.gamma,
+                            scale: args
+                                .fit
+                                .scale(camera1.cropped_size(), (tile_width, tile_height)),
+                            crop: camera1.crop,
+                            vignette: [args.vignette, args.vignette_radius],
+                        };
+                        let params = glium::DrawParameters {
+                            viewport: Some(viewport),
+                            ..Default::default()
+                        };
+                        draw(&program_camera1, texture, eye, &params);
+                    }
+                    if let Some(texture) = &texture2 {
+                        let eye = Eye {
+                            offset: [
+                                camera2_shift[0] + (1.0 - t) * settings.convergence,
+                                camera2_shift[1],
+                            ],
+                            roll: roll2,
+                            magnification: args.camera2_scale,
+                            gain: settings.camera2_gains,
+                            ycbcr: camera2.ycbcr,
+                            brightness: settings.brightness * exposure[1] * settings.eye_gains[1],
+                            gamma: settings.gamma,
+                            scale: args
+                                .fit
+                                .scale(camera2.cropped_size(), (tile_width, tile_height)),
+                            crop: camera2.crop,
+                            vignette: [args.vignette, args.vignette_radius],
+                        };
+                        let fade = glium::BlendingFunction::Addition {
+                            source: glium::LinearBlendingFactor::ConstantAlpha,
+                            destination: glium::LinearBlendingFactor::OneMinusConstantAlpha,
+                        };
+                        let params = glium::DrawParameters {
+                            blend: glium::Blend {
+                                color: fade,
+                                alpha: fade,
+                                constant_value: (0.0, 0.0, 0.0, t),
+                            },
+                            viewport: Some(viewport),
+                            ..Default::default()
+                        };
+                        draw(&program_camera2, texture, eye, &params);
+                    }
+                    check_gl("multiview draw");
+                }
+            }
+            Mode::Disparity => {
+                let map = disparity::heatmap(
+                    camera1.frame(),
+                    camera2.frame(),
+                    settings.camera2_offset[1],
+                );
+                if map.width > 0 && map.height > 0 {
+                    let image = glium::texture::RawImage2d::from_raw_rgb_reversed(
+                        &map.data,
+                        (map.width, map.height),
+                    );
+                    let texture = glium::texture::Texture2d::new(&display, image).unwrap();
+                    // drawn like camera1 so it's flipped the same way
+                    let eye = Eye {
+                        offset: [0.0, 0.0],
+                        roll: 0.0,
+                        magnification: 1.0,
+                        gain: [1.0; 3],
+                        ycbcr: false,
+                        brightness: 1.0,
+                        gamma: 1.0,
+                        scale: args.fit.scale(camera1.size(), (width, height)),
+                        crop: FULL_FRAME,
+                        vignette: [args.vignette, args.vignette_radius],
+                    };
+                    draw(&program_camera1, &texture, eye, &Default::default());
+                    check_gl("disparity draw");
+                }
+            }
+        }
+
+        if let Some(window2) = &mut window2 {
+            let [eye1, eye2] = eyes(
+                &args,
+                &settings,
+                [&camera1, &camera2],
+                exposure,
+                window2.eye_size(),
+            );
+            let views = [
+                (&program_camera1, texture1, eye1, mask1),
+                (&program_camera2, texture2, eye2, mask2),
+            ]
+            .map(|(program, texture, eye, mask)| {
+                texture.map(|texture| second_window::View {
+                    program,
+                    texture,
+                    eye,
+                    mask,
+                })
+            });
+            let quad = (&vertex_buffer, &index_buffer);
+            if let Err(error) = window2.draw(&display, quad, views, args.edge) {
+                eprintln!("\nwindow2: {error:#}");
+            }
+            check_gl("window2 draw");
+        }
+
+        let no_signal = args
+            .no_signal_pattern
+            .filter(|_| live)
+            .map(Duration::from_secs_f32)
+            .is_some_and(|timeout| {
+                // unlike stalled_for(), not reset by the watchdog restarting the capture
+                [&camera1, &camera2].iter().all(|camera| {
+                    camera.captured.lock().unwrap().unwrap_or(started).elapsed() > timeout
+                })
+            });
+        if no_signal {
+            let uniforms = uniform! { time: started.elapsed().as_secs_f32() };
+            target
+                .draw(
+                    &vertex_buffer,
+                    &index_buffer,
+                    &program_no_signal,
+                    &uniforms,
+                    &glium::DrawParameters {
+                        viewport: Some(area),
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+            check_gl("no signal draw");
+        }
+
+        if let Some(cvd) = args.simulate_cvd {
+            let composited = glium::texture::Texture2d::empty(&display, width, height).unwrap();
+            let whole = glium::BlitTarget {
+                left: 0,
+                bottom: 0,
+                width: width as i32,
+                height: height as i32,
+            };
+            target.blit_color(
+                &area,
+                &composited.as_surface(),
+                &whole,
+                glium::uniforms::MagnifySamplerFilter::Nearest,
+            );
+            let uniforms = uniform! { image: &composited, cvd: cvd.matrix() };
+            target
+                .draw(
+                    &vertex_buffer,
+                    &index_buffer,
+                    &program_cvd,
+                    &uniforms,
+                    &glium::DrawParameters {
+                        viewport: Some(area),
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+            check_gl("color vision draw");
+        }
+
+        let t1 = Instant::now();
+
+        if !sinks.is_empty() {
+            let unscaled;
+            let sent = match &scaled_output {
+                Some(scaled) => scaled,
+                None => {
+                    let (width, height) = (area.width.max(1), area.height.max(1));
+                    unscaled = glium::texture::Texture2d::empty(&display, width, height).unwrap();
+                    &unscaled
+                }
+            };
+            let whole = glium::BlitTarget {
+                left: 0,
+                bottom: 0,
+                width: sent.width() as i32,
+                height: sent.height() as i32,
+            };
+            let filter = glium::uniforms::MagnifySamplerFilter::Linear;
+            target.blit_color(&area, &sent.as_surface(), &whole, filter);
+            let frame: glium::texture::RawImage2d<u8> = sent.read();
+            check_gl("readback");
+            output::write_all(&mut sinks, &frame.data, frame.width, frame.height);
+        }
+        window_target.blit_from_simple_framebuffer(
+            &target,
+            &area,
+            &glium::BlitTarget {
+                left: area.left,
+                bottom: area.bottom,
+                width: area.width as i32,
+                height: area.height as i32,
+            },
+            glium::uniforms::MagnifySamplerFilter::Nearest,
+        );
+        check_gl("composited blit");
+        drop(target);
+        let mut target = window_target;
+
+        let color_scheme = settings.color_scheme;
+        let camera2_size = camera2.size();
+        let interval = last_render.elapsed();
+        last_render = Instant::now();
+        graph.record(
+            interval,
+            [&camera1, &camera2].map(|camera| *camera.captured.lock().unwrap()),
+        );
+        if let Err(error) = graph.draw(&display, &mut target, area) {
+            eprintln!("\ngraph: {error:#}");
+        }
+        check_gl("graph draw");
+        let interval = interval.as_secs_f32();
+        if interval > 0.0 {
+            fps = 0.9 * fps + 0.1 / interval;
+        }
+        panel.draw(
+            &display,
+            &window,
+            &mut target,
+            &mut settings,
+            camera2_size,
+            fps,
+        );
+        if settings.color_scheme != color_scheme {
+            window.set_title(&window_title(&args, mode, settings.color_scheme));
+        }
+
+        if let Some(latency) = &mut latency {
+            let now = Instant::now();
+            for (camera, (shown, measured)) in shown.iter().zip(&mut measured).enumerate() {
+                // only the first presentation of each frame counts
+                if let Some(captured) = shown.filter(|_| shown != measured) {
+                    latency.record(camera, now - captured);
+                }
+                *measured = *shown;
+            }
+            if let Some(summary) = latency.summary() {
+                println!("\n{summary}");
+            }
+        }
+
+        target.finish().unwrap();
+        check_gl("buffer swap");
+        if let Some(sync_gpio) = &mut sync_gpio {
+            if !shown.contains(&None) && (0..2).all(|eye| shown[eye] != synced[eye]) {
+                sync_gpio.toggle();
+                synced = shown;
+            }
+        }
+        render_ms.record(t0.elapsed().as_secs_f32() * 1000.0);
+        match args.power_mode {
+            PowerMode::Performance => (),
+            PowerMode::Balanced => elwt.set_control_flow(
+                winit::event_loop::ControlFlow::WaitUntil(t0 + refresh_interval),
+            ),
+            // still wake up now and then for the stall watchdog and the no signal pattern
+            PowerMode::PowerSave => elwt.set_control_flow(
+                winit::event_loop::ControlFlow::WaitUntil(t0 + Duration::from_secs(1)),
+            ),
+        }
+
+        if args.stats {
+            let line = format!(
+                "ms: {}\t (buffer) + {}\t (UI)",
+                t1.duration_since(t0).as_millis(),
+                t0.elapsed().as_millis()
+            );
+            if std::io::stderr().is_terminal() {
+                // back to the start of the line and clear it
+                eprint!("\r\x1b[K{line}");
+            } else {
+                eprintln!("{line}");
+            }
+        }
+    })?;
+    Ok(())
+}
+
+/// Where a point in the window, from 0 to 1 with the origin at the top left, is in a camera's
+/// frames drawn at `scale` with the given flips, ignoring the smaller shifts and rotations.
+fn window_to_image(point: [f32; 2], scale: [f32; 2], flip_x: bool, flip_y: bool) -> [f32; 2] {
+    let [mut x, mut y] =
+        [0, 1].map(|axis| ((point[axis] - 0.5) / scale[axis] + 0.5).clamp(0.0, 1.0));
+    if flip_y {
+        [x, y] = [1.0 - x, 1.0 - y];
+    }
+    if flip_x {
+        x = 1.0 - x;
+    }
+    [x, y]
+}
+
+/// Average red, green and blue of a camera's current frame, sampling every `step`th pixel.
+fn rgb_means(camera: &mut Camera, step: usize) -> [f32; 3] {
+    let frame = camera.frame();
+    let means = match frame.pixel_format {
+        PixelFormat::Bayer(bayer) => {
+            return color::bayer_means(&frame.data, frame.width, bayer.red(), step)
+        }
+        PixelFormat::Rgb | PixelFormat::Ycbcr => color::channel_means(&frame.data, step),
+    };
+    if frame.pixel_format == PixelFormat::Ycbcr {
+        color::ycbcr_to_rgb(means)
+    } else {
+        means
+    }
+}
+
+/// For --auto-eye-order, whether camera1 seems to be the right one judging by the disparity
+/// between the cameras' first frames.
+fn cameras_swapped(args: &Cli, camera1: &mut Camera, camera2: &mut Camera, live: bool) -> bool {
+    let timeout = Duration::from_secs(5);
+    if live && !(camera1.wait_for_frame(timeout) && camera2.wait_for_frame(timeout)) {
+        eprintln!("WARNING: no frames to check the eye order with, keeping it");
+        return false;
+    }
+    match disparity::swapped(camera1.frame(), camera2.frame(), args.camera2_offset_y) {
+        Some(true) => {
+            println!("camera1 looks like the right camera, swapping eyes");
+            true
+        }
+        Some(false) => {
+            println!("The eye order looks right");
+            false
+        }
+        None => {
+            eprintln!("WARNING: couldn't tell the eye order from the cameras' frames, keeping it");
+            false
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct Vertex {
+    position: [f32; 2],
+    tex_coords: [f32; 2],
+}
+
+implement_vertex!(Vertex, position, tex_coords);
+
+/// The full-viewport quad every pass draws, as a triangle strip.
+fn quad(
+    display: &impl glium::backend::Facade,
+) -> (glium::VertexBuffer<Vertex>, glium::IndexBuffer<u16>) {
+    // the following OpenGL code is inspired by
+    // <https://github.com/raymanfx/libv4l-rs/blob/ced9df0bb2ab3c1b03783536fceb209a630d23c8/examples/glium.rs>
+    // which is licensed under the MIT license
+    let vertex_buffer = glium::VertexBuffer::new(
+        display,
+        &[
+            Vertex {
+                position: [-1.0, -1.0],
+                tex_coords: [0.0, 0.0],
+            },
+            Vertex {
+                position: [-1.0, 1.0],
+                tex_coords: [0.0, 1.0],
+            },
+            Vertex {
+                position: [1.0, 1.0],
+                tex_coords: [1.0, 1.0],
+            },
+            Vertex {
+                position: [1.0, -1.0],
+                tex_coords: [1.0, 0.0],
+            },
+        ],
+    )
+    .unwrap();
+    let index_buffer =
+        glium::IndexBuffer::new(display, PrimitiveType::TriangleStrip, &[1u16, 2, 0, 3]).unwrap();
+    (vertex_buffer, index_buffer)
+}
+
+/// The shader options of a camera, 0 or 1, whose frames are raw `bayer` data if it's set.
+fn camera_shader_opts(
+    args: &Cli,
+    camera: usize,
+    bayer: Option<camera::Bayer>,
+) -> shaders::ShaderOpts {
+    shaders::ShaderOpts {
+        flip_x: args.flip_x,
+        flip_y: [args.camera1_flip_y, args.camera2_flip_y][camera],
+        deinterlace: [args.camera1_deinterlace, args.camera2_deinterlace][camera],
+        bayer,
+        transparent: args.transparent,
+        linear: args.linear_blend,
+        vignette: args.vignette > 0.0,
+    }
+}
+
+/// The program drawing one camera's image with the given startup options.
+fn build_camera_program(
+    display: &impl glium::backend::Facade,
+    opts: &shaders::ShaderOpts,
+) -> anyhow::Result<glium::Program> {
+    shaders::compile_with_output(
+        display,
+        "camera",
+        shaders::CAMERA_VERTEX,
+        &shaders::fragment_shader(opts),
+        opts.linear,
+    )
+}
+
+/// [`Eye::crop`] showing the whole image.
+const FULL_FRAME: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+/// Where and how one camera's image is sampled for a draw call.
+#[derive(Debug, Clone, Copy)]
+struct Eye {
+    /// Shift in texels, x to the right and y down.
+    offset: [f32; 2],
+    /// Rotation about the image center in degrees.
+    roll: f32,
+    /// Scale about the image center, above 1 to zoom in.
+    magnification: f32,
+    /// Per-channel color correction.
+    gain: [f32; 3],
+    /// The texture holds YCbCr rather than RGB.
+    ycbcr: bool,
+    brightness: f32,
+    gamma: f32,
+    /// Size of the drawn quad relative to the viewport, from `--fit`.
+    scale: [f32; 2],
+    /// The part of the image that's shown, see [`Camera::crop`].
+    crop: [f32; 4],
+    /// Strength and radius of the vignette, if the program has one.
+    vignette: [f32; 2],
+}
+
+/// The window's size of texture the anaglyph is drawn into, which is then copied into the
+/// window and read back for the output sinks; sRGB with --linear-blend, like the window.
+enum Composited {
+    Rgb(glium::texture::Texture2d),
+    Srgb(glium::texture::SrgbTexture2d),
+}
+
+impl Composited {
+    fn new(
+        facade: &impl glium::backend::Facade,
+        srgb: bool,
+        (width, height): (u32, u32),
+    ) -> anyhow::Result<Self> {
+        Ok(if srgb {
+            Composited::Srgb(glium::texture::SrgbTexture2d::empty(facade, width, height)?)
+        } else {
+            Composited::Rgb(glium::texture::Texture2d::empty(facade, width, height)?)
+        })
+    }
+
+    fn dimensions(&self) -> (u32, u32) {
+        match self {
+            Composited::Rgb(texture) => texture.dimensions(),
+            Composited::Srgb(texture) => texture.dimensions(),
+        }
+    }
+
+    fn framebuffer(
+        &self,
+        facade: &impl glium::backend::Facade,
+    ) -> glium::framebuffer::SimpleFrameBuffer<'_> {
+        match self {
+            Composited::Rgb(texture) => glium::framebuffer::SimpleFrameBuffer::new(facade, texture),
+            Composited::Srgb(texture) => {
+                glium::framebuffer::SimpleFrameBuffer::new(facade, texture)
+            }
+        }
+        .unwrap()
+    }
+}
+
+/// The part of a window of `size` that's drawn into, `--viewport` cut down to the window.
+fn viewport(viewport: Option<&[u32]>, (width, height): (u32, u32)) -> glium::Rect {
+    match viewport {
+        Some(&[x, y, w, h]) => {
+            let (x, y) = (x.min(width), y.min(height));
+            let (w, h) = (w.min(width - x), h.min(height - y));
+            // GL's origin is the bottom left
+            glium::Rect {
+                left: x,
+                bottom: height - y - h,
+                width: w,
+                height: h,
+            }
+        }
+        _ => glium::Rect {
+            left: 0,
+            bottom: 0,
+            width,
+            height,
+        },
+    }
+}
+
+/// Both cameras as they're drawn in the anaglyph and overlay modes, into a viewport of `size`.
+fn eyes(
+    args: &Cli,
+    settings: &settings::Settings,
+    [camera1, camera2]: [&Camera; 2],
+    exposure: [f32; 2],
+    size: (u32, u32),
+) -> [Eye; 2] {
+    // what the cameras didn't already rotate themselves
+    let [roll1, roll2] = [(camera1, args.camera1_roll), (camera2, args.camera2_roll)]
+        .map(|(camera, roll)| if camera.rotated { 0.0 } else { roll });
+    let eye1 = Eye {
+        offset: [settings.convergence / 2.0, 0.0],
+        roll: roll1,
+        magnification: args.camera1_scale,
+        gain: settings.camera1_gains,
+        ycbcr: camera1.ycbcr,
+        brightness: settings.brightness * exposure[0] * settings.eye_gains[0],
+        gamma: settings.gamma,
+        scale: args.fit.scale(camera1.cropped_size(), size),
+        crop: camera1.crop,
+        vignette: [args.vignette, args.vignette_radius],
+    };
+    let eye2 = Eye {
+        offset: [
+            settings.camera2_offset[0] as f32 + settings.depth_bias - settings.convergence / 2.0,
+            settings.camera2_offset[1] as f32,
+        ],
+        roll: roll2,
+        magnification: args.camera2_scale,
+        gain: settings.camera2_gains,
+        ycbcr: camera2.ycbcr,
+        brightness: settings.brightness * exposure[1] * settings.eye_gains[1],
+        gamma: settings.gamma,
+        scale: args.fit.scale(camera2.cropped_size(), size),
+        crop: camera2.crop,
+        vignette: [args.vignette, args.vignette_radius],
+    };
+    [eye1, eye2]
+}
+
+/// Uploads a frame, raw Bayer data as a single channel, or returns `None` if it's incomplete,
+/// e.g. because the camera hasn't delivered one yet.
+fn frame_texture(
+    facade: &impl glium::backend::Facade,
+    frame: &Frame,
+    texture_format: TextureFormat,
+) -> Option<glium::texture::Texture2d> {
+    if !frame.is_complete() {
+        return None;
+    }
+    let (data, width, height) = (&frame.data, frame.width, frame.height);
+    let raw = matches!(frame.pixel_format, PixelFormat::Bayer(_));
+
+    let image = if raw {
+        // uploaded as a single channel for the shader to demosaic, bottom row first
+        glium::texture::RawImage2d {
+            data: data
+                .chunks_exact(width as usize)
+                .rev()
+                .flatten()
+                .copied()
+                .collect(),
+            width,
+            height,
+            format: glium::texture::ClientFormat::U8,
+        }
+    } else {
+        glium::texture::RawImage2d::from_raw_rgb_reversed(data, (width, height))
+    };
+    let texture = match texture_format.internal() {
+        Some(internal) if !raw => glium::texture::Texture2d::with_format(
+            facade,
+            image,
+            internal,
+            glium::texture::MipmapsOption::NoMipmap,
+        ),
+        _ => glium::texture::Texture2d::new(facade, image),
+    };
+    Some(texture.unwrap())
+}
+
+/// The mean of `frames`, mixing each one into the mean of the ones before it.
+fn average(
+    facade: &impl glium::backend::Facade,
+    (vertex_buffer, index_buffer): (&glium::VertexBuffer<Vertex>, &glium::IndexBuffer<u16>),
+    program_mix: &glium::Program,
+    frames: &VecDeque<glium::texture::Texture2d>,
+) -> Option<glium::texture::Texture2d> {
+    let mut mean: Option<glium::texture::Texture2d> = None;
+    for (index, frame) in frames.iter().enumerate() {
+        let (width, height) = frame.dimensions();
+        let mixed = glium::texture::Texture2d::empty(facade, width, height).unwrap();
+        glium::framebuffer::SimpleFrameBuffer::new(facade, &mixed)
+            .unwrap()
+            .draw(
+                vertex_buffer,
+                index_buffer,
+                program_mix,
+                &uniform! {
+                    current: frame,
+                    previous: mean.as_ref().unwrap_or(frame),
+                    // the mean so far covers `index` frames
+                    strength: index as f32 / (index + 1) as f32,
+                },
+                &Default::default(),
+            )
+            .unwrap();
+        mean = Some(mixed);
+    }
+    mean
+}
+
+fn camera_uniforms(
+    texture: &glium::texture::Texture2d,
+    eye: Eye,
+    edge: Edge,
+) -> impl glium::uniforms::Uniforms + '_ {
+    let [x, y, crop_width, crop_height] = eye.crop;
+    let (width, height) = texture.dimensions();
+    let (width, height) = (width as f32 * crop_width, height as f32 * crop_height);
+    uniform! {
+        matrix: [
+            [eye.scale[0], 0.0, 0.0, 0.0],
+            [0.0, eye.scale[1], 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0f32]
+        ],
+        tex: texture.sampled().wrap_function(edge.wrap_function()),
+        // textures are bottom up
+        crop: [x, 1.0 - y - crop_height, crop_width, crop_height],
+        // texels to texture coordinates; texture y points up, offsets point down
+        offset: [eye.offset[0] / width, -eye.offset[1] / height],
+        roll: eye.roll.to_radians(),
+        magnification: eye.magnification,
+        gain: eye.gain,
+        ycbcr: eye.ycbcr,
+        brightness: eye.brightness,
+        gamma: eye.gamma,
+        vignette: eye.vignette,
+    }
+}
+
+/// Channel masks for camera1 and camera2, swapped for `--selfie` rigs.
+fn eye_masks(args: &Cli, color_scheme: ColorScheme) -> [ChannelMask; 2] {
+    let [left, right] = color_scheme.masks();
+    if args.selfie {
+        [right, left]
+    } else {
+        [left, right]
+    }
+}
+
+/// The resolution after `current` among those both cameras offer, smallest first, for R.
+///
+/// A camera offering none, like a socket source, doesn't say what it supports, so it's left
+/// to reject sizes when asked.
+fn next_resolution(current: (u32, u32), [first, second]: [&[(u32, u32)]; 2]) -> Option<(u32, u32)> {
+    let (first, second) = if first.is_empty() {
+        (second, first)
+    } else {
+        (first, second)
+    };
+    let common: Vec<_> = first
+        .iter()
+        .filter(|size| second.is_empty() || second.contains(size))
+        .collect();
+    let next = common
+        .iter()
+        .position(|&&size| size == current)
+        .map_or(0, |index| (index + 1) % common.len());
+    common.get(next).copied().copied()
+}
+
+/// Describes the mode, inputs and color scheme, e.g.
+/// `anaglyph: /dev/video0 + /dev/video2 [red-cyan]`.
+fn window_title(args: &Cli, mode: Mode, color_scheme: ColorScheme) -> String {
+    let inputs = match (&args.mpo, &args.sbs) {
+        (Some(path), _) | (_, Some(path)) => path.display().to_string(),
+        _ => {
+            let [camera1, camera2] = args.inputs();
+            format!("{camera1} + {camera2}")
+        }
+    };
+    let mode = clap::ValueEnum::to_possible_value(&mode).expect("no mode is skipped");
+    format!("{}: {inputs} [{color_scheme}]", mode.get_name())
+}
+
+/// Asks on the terminal which capture devices to use as camera1 and camera2.
+fn pick_cameras(args: &mut Cli) -> anyhow::Result<()> {
+    let mut devices = camera::capture_devices();
+    anyhow::ensure!(
+        devices.len() >= 2,
+        "need two capture devices, found {}",
+        devices.len()
+    );
+    for (name, path) in [
+        ("camera1", &mut args.camera1),
+        ("camera2", &mut args.camera2),
+    ] {
+        let items: Vec<_> = devices
+            .iter()
+            .map(|(path, card)| format!("{path} ({card})"))
+            .collect();
+        let choice = dialoguer::Select::new()
+            .with_prompt(format!("Device for {name}"))
+            .items(&items)
+            .default(0)
+            .interact()?;
+        *path = devices.remove(choice).0;
+    }
+    Ok(())
+}
+
+/// Replaces default camera paths that don't exist with the lowest-numbered capture devices that
+/// weren't picked explicitly, since device numbers differ between systems and boots.
+fn pick_default_cameras(args: &mut Cli, matches: &ArgMatches) -> anyhow::Result<()> {
+    let is_missing_default = |id: &str, input: Input| match input {
+        Input::Device(path) => {
+            matches.value_source(id) == Some(ValueSource::DefaultValue) && !Path::new(path).exists()
+        }
+        _ => false,
+    };
+    let [input1, input2] = args.inputs();
+    let missing = [
+        is_missing_default("camera1", input1),
+        is_missing_default("camera2", input2),
+    ];
+    if missing == [false, false] {
+        return Ok(());
+    }
+
+    let devices = camera::capture_devices();
+    let taken = [args.camera1.clone(), args.camera2.clone()];
+    let mut available = devices
+        .iter()
+        .map(|(path, _)| path)
+        .filter(|path| !taken.contains(path));
+    for (name, path, missing) in [
+        ("camera1", &mut args.camera1, missing[0]),
+        ("camera2", &mut args.camera2, missing[1]),
+    ] {
+        if !missing {
+            continue;
+        }
+        let Some(device) = available.next() else {
+            let mut found = devices
+                .iter()
+                .map(|(path, card)| format!("  {path} ({card})"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            if found.is_empty() {
+                found = "  none".to_owned();
+            }
+            anyhow::bail!(
+                "{path} doesn't exist and there's no other capture device left for {name}; \
+                 capture devices found:\n{found}\n\
+                 pass the devices to use as `anaglyph_robot <CAMERA1> <CAMERA2>`"
+            );
+        };
+        println!("{path} doesn't exist, using {device} as {name}");
+        *path = device.clone();
+    }
+    Ok(())
+}
+
+/// The options every mode honours, which set up the cameras or don't render at all; those a
+/// mode doesn't and clap doesn't already reject alongside it are warned about, see
+/// [`ignored_options`].
+const SETUP_OPTIONS: &[&str] = &[
+    "camera1",
+    "camera1_flip_y",
+    "camera1_socket",
+    "camera1_file",
+    "camera1_bayer",
+    "camera1_decimate",
+    "camera2",
+    "camera2_flip_y",
+    "camera2_socket",
+    "camera2_file",
+    "camera2_bayer",
+    "camera2_decimate",
+    "flip_x",
+    "selfie",
+    "color_scheme",
+    "width",
+    "height",
+    "strict_resolution",
+    "io_method",
+    "open_retries",
+    "open_retry_delay_ms",
+    "stereo_camera",
+    "pick",
+    "pin_threads",
+    "verbose",
+    "probe",
+    "benchmark_decode",
+    "benchmark_iterations",
+    "decode_threads",
+    "dump_shaders",
+    "dry_run",
+    "trigger_mode",
+    "mpo",
+    "sbs",
+    "load_calibration",
+    "metrics_json",
+];
+
+/// The options --cpu-render honours besides [`SETUP_OPTIONS`].
+const CPU_RENDER_OPTIONS: &[&str] = &["cpu_render"];
+
+/// The options the offscreen compositor of --once and --headless honours besides
+/// [`SETUP_OPTIONS`].
+const COMPOSITOR_OPTIONS: &[&str] = &[
+    "camera1_roll",
+    "camera1_scale",
+    "camera1_gains",
+    "camera1_deinterlace",
+    "camera1_hw_crop",
+    "camera2_roll",
+    "camera2_scale",
+    "camera2_gains",
+    "camera2_deinterlace",
+    "camera2_hw_crop",
+    "camera2_offset_x",
+    "camera2_offset_y",
+    "fit",
+    "edge",
+    "vignette",
+    "vignette_radius",
+    "brightness",
+    "gamma",
+    "eye1_gain",
+    "eye2_gain",
+    "convergence",
+    "depth_bias",
+    "clahe",
+    "gpu_color_convert",
+    "texture_format",
+    "output_width",
+    "output_height",
+];
+
+/// The options --once honours besides [`SETUP_OPTIONS`] and [`COMPOSITOR_OPTIONS`].
+const ONCE_OPTIONS: &[&str] = &["once", "output", "once_timeout"];
+
+/// The options --headless honours besides [`SETUP_OPTIONS`] and [`COMPOSITOR_OPTIONS`].
+const HEADLESS_OPTIONS: &[&str] = &[
+    "headless",
+    "print_latency",
+    "output_v4l2",
+    "output_pipe",
+    "output_pipe_format",
+    "output_quality",
+];
+
+/// The options given on the command line or in the environment that aren't in any of the
+/// `honoured` lists.
+fn ignored_options(matches: &clap::ArgMatches, honoured: &[&[&str]]) -> Vec<String> {
+    let honoured = honoured.concat();
+    let mut ignored: Vec<_> = Cli::command()
+        .get_arguments()
+        .map(|arg| arg.get_id().as_str())
+        .filter(|id| !honoured.concat().contains(id))
+        .filter(|id| {
+            matches!(
+                matches.value_source(id),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        })
+        .map(|id| format!("--{}", id.replace('_', "-")))
+        .collect();
+    ignored.sort();
+    ignored
+}
+
+/// Writes the cameras' statistics and `render_ms` to --metrics-json if it's given, on leaving
+/// any of the modes that render.
+fn write_metrics(
+    args: &Cli,
+    stats: [&Mutex<metrics::CaptureStats>; 2],
+    render_ms: &metrics::Reservoir,
+) {
+    let Some(path) = &args.metrics_json else {
+        return;
+    };
+    let [stats1, stats2] = stats.map(|stats| stats.lock().unwrap());
+    let cameras = [(&*args.camera1, &*stats1), (&*args.camera2, &*stats2)];
+    if let Err(error) = metrics::write_json(path, &cameras, render_ms) {
+        eprintln!("\nfailed to write {}: {error:#}", path.display());
+    }
+}
+
+/// Lets every argument also be given as an environment variable named after it, e.g.
+/// `ANAGLYPH_CAMERA1` or `ANAGLYPH_COLOR_SCHEME`, for containers and systemd units.
+fn with_env_fallback(command: clap::Command) -> clap::Command {
+    command
+        .mut_args(|arg| {
+            let name = format!("ANAGLYPH_{}", arg.get_id().as_str().to_uppercase());
+            arg.env(name)
+        })
+        .after_help(
+            "Every argument can also be set with the environment variable shown next to it; \
+             the command line takes precedence over the environment, which takes precedence \
+             over the defaults.",
+        )
+}
+
+fn parse_gl_version(version: &str) -> Result<(u8, u8), String> {
+    let (major, minor) = version
+        .split_once('.')
+        .ok_or_else(|| format!("expected MAJOR.MINOR, like 3.3, got {version}"))?;
+    let number = |n: &str| n.parse::<u8>().map_err(|e| format!("{n}: {e}"));
+    Ok((number(major)?, number(minor)?))
+}
+
+fn parse_finite(number: &str) -> Result<f32, String> {
+    match number.parse::<f32>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        Ok(_) => Err(format!("expected a finite number, got {number}")),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+/// The core the capture thread of camera1 (0), camera2 (1) or the render thread (2) is pinned
+/// to with `--pin-threads`.
+fn thread_core(thread: usize, args: &Cli) -> Option<core_affinity::CoreId> {
+    if !args.pin_threads {
+        return None;
+    }
+    core_affinity::get_core_ids()?.get(thread).copied()
+}
+
+/// Where composited frames are sent besides the window.
+fn output_sinks(
+    args: &Cli,
+    pipe: Option<pipe::Pipe>,
+) -> anyhow::Result<Vec<Box<dyn output::OutputSink>>> {
+    let mut sinks: Vec<Box<dyn output::OutputSink>> = Vec::new();
+    for path in &args.output_v4l2 {
+        sinks.push(Box::new(loopback::Loopback::open(path)?));
+    }
+    if let Some(pipe) = pipe {
+        sinks.push(Box::new(pipe));
+    }
+    Ok(sinks)
+}
+
+fn capture_options(camera: usize, args: &Cli) -> CaptureOptions {
+    CaptureOptions {
+        width: args.width,
+        height: args.height,
+        strict_resolution: args.strict_resolution,
+        ycbcr: args.gpu_color_convert,
+        verbose: args.verbose,
+        core: thread_core(camera, args),
+        roll: [args.camera1_roll, args.camera2_roll][camera],
+        bayer: [args.camera1_bayer, args.camera2_bayer][camera],
+        decimate: [args.camera1_decimate, args.camera2_decimate][camera],
+        io_method: args.io_method,
+        busy_retry: camera::Retry {
+            attempts: args.open_retries,
+            delay: Duration::from_millis(args.open_retry_delay_ms),
+        },
+        crop: [&args.camera1_hw_crop, &args.camera2_hw_crop][camera]
+            .as_deref()
+            .and_then(|crop| crop.try_into().ok()),
+    }
+}
+
+fn open_camera(index: usize, input: Input, args: &Cli) -> anyhow::Result<Camera> {
+    let name = input.to_string();
+    let (width, height) = (args.width, args.height);
+    match input {
+        Input::Device(path) => Ok(cam(path, capture_options(index, args))?),
+        Input::Socket(path) => Ok(Camera::from_source(
+            SocketSource::bind(path, width, height)?,
+            &name,
+            width,
+            height,
+        )),
+        Input::File(path) => Ok(Camera::from_source(
+            FileSource::open(path, width, height)?,
+            &name,
+            width,
+            height,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::ValueEnum;
+
+    const WIDTH: u32 = 16;
+    const HEIGHT: u32 = 12;
+
+    /// A frame whose colors differ along both axes and between the eyes, so flips and
+    /// channels taken from the wrong eye change the result.
+    fn test_card(eye: u8) -> Vec<u8> {
+        (0..HEIGHT)
+            .flat_map(|y| (0..WIDTH).map(move |x| (x, y)))
+            .flat_map(|(x, y)| [x as u8 * 15, y as u8 * 20, 40 + eye * 100 + (x + y) as u8])
+            .collect()
+    }
+
+    /// Draws both eyes like the anaglyph mode does without crosstalk cancellation, returning
+    /// interleaved RGB rows from the top.
+    fn render_anaglyph(
+        context: &std::rc::Rc<glium::backend::Context>,
+        scheme: ColorScheme,
+        flips: [bool; 3],
+    ) -> Vec<u8> {
+        let [flip_x, flip_y1, flip_y2] = flips;
+        let (vertex_buffer, index_buffer) = quad(context);
+        let target = glium::texture::Texture2d::empty(context, WIDTH, HEIGHT).unwrap();
+        let mut framebuffer = glium::framebuffer::SimpleFrameBuffer::new(context, &target).unwrap();
+        framebuffer.clear_color(0.0, 0.0, 0.0, 0.0);
+        for ((eye, flip_y), mask) in [0, 1]
+            .into_iter()
+            .zip([flip_y1, flip_y2])
+            .zip(scheme.masks())
+        {
+            let opts = shaders::ShaderOpts {
+                flip_x,
+                flip_y,
+                ..Default::default()
+            };
+            let program = build_camera_program(context, &opts).unwrap();
+            let image =
+                glium::texture::RawImage2d::from_raw_rgb_reversed(&test_card(eye), (WIDTH, HEIGHT));
+            let texture = glium::texture::Texture2d::new(context, image).unwrap();
+            let eye = Eye {
+                offset: [0.0, 0.0],
+                roll: 0.0,
+                magnification: 1.0,
+                gain: [1.0; 3],
+                ycbcr: false,
+                brightness: 1.0,
+                gamma: 1.0,
+                scale: [1.0, 1.0],
+                crop: FULL_FRAME,
+                vignette: [0.0, 0.0],
+            };
+            let params = glium::DrawParameters {
+                color_mask: mask.color_mask(),
+                ..Default::default()
+            };
+            framebuffer
+                .draw(
+                    &vertex_buffer,
+                    &index_buffer,
+                    &program,
+                    &camera_uniforms(&texture, eye, Edge::Border),
+                    &params,
+                )
+                .unwrap();
+        }
+        let rows: Vec<Vec<(u8, u8, u8, u8)>> = target.read();
+        rows.iter()
+            .rev()
+            .flatten()
+            .flat_map(|&(r, g, b, _)| [r, g, b])
+            .collect()
+    }
+
+    #[test]
+    fn anaglyph_matches_cpu_compositor() {
+        let Some(context) = display::headless() else {
+            eprintln!("skipping, no EGL device to render on");
+            return;
+        };
+        let cards = [test_card(0), test_card(1)];
+        for &scheme in ColorScheme::value_variants() {
+            for flips in [
+                [false; 3],
+                [true, false, false],
+                [false, true, false],
+                [true, false, true],
+            ] {
+                let rendered = render_anaglyph(&context, scheme, flips);
+
+                // the software renderer samples the same way, one texel per pixel
+                let eyes: Vec<_> = cards
+                    .iter()
+                    .zip([flips[1], flips[2]])
+                    .zip(scheme.masks())
+                    .map(|((data, flip_y), mask)| cpu::Eye {
+                        data,
+                        width: WIDTH,
+                        height: HEIGHT,
+                        options: cpu::EyeOptions {
+                            flip_x: flips[0],
+                            flip_y,
+                        },
+                        mask,
+                    })
+                    .collect();
+                let mut expected = vec![0; (WIDTH * HEIGHT) as usize];
+                cpu::composite(&eyes, &mut expected, WIDTH, HEIGHT);
+                let expected = expected
+                    .iter()
+                    .flat_map(|pixel| [pixel >> 16, pixel >> 8, *pixel].map(|c| c as u8));
+
+                for (i, (rendered, expected)) in rendered.iter().zip(expected).enumerate() {
+                    assert!(
+                        rendered.abs_diff(expected) <= 2,
+                        "{scheme:?} with flips {flips:?}: channel {i} is {rendered}, expected \
+                         {expected}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn resolutions_step_through_the_common_ones() {
+        let camera1 = vec![(320, 240), (640, 480), (800, 600), (1280, 720)];
+        let camera2 = vec![(320, 240), (640, 480), (1280, 720)];
+        let next = |current| next_resolution(current, [&camera1[..], &camera2]);
+        assert_eq!(next((640, 480)), Some((1280, 720)));
+        assert_eq!(next((1280, 720)), Some((320, 240)));
+        // not one of the common ones, e.g. after the driver picked another size
+        assert_eq!(next((800, 600)), Some((320, 240)));
+        assert_eq!(
+            next_resolution((640, 480), [&[], &camera2]),
+            Some((1280, 720))
+        );
+        assert_eq!(next_resolution((640, 480), [&camera1, &[(1, 1)]]), None);
+    }
+
+    #[test]
+    fn modes_warn_about_the_options_they_ignore() {
+        let command = Cli::command();
+        let lists = [
+            SETUP_OPTIONS,
+            CPU_RENDER_OPTIONS,
+            COMPOSITOR_OPTIONS,
+            ONCE_OPTIONS,
+            HEADLESS_OPTIONS,
+        ];
+        for id in lists.concat() {
+            assert!(
+                command.get_arguments().any(|arg| arg.get_id() == id),
+                "no option {id}"
+            );
+        }
+        let ignored = |mode: &[&str], honoured: &[&[&str]]| {
+            let options = [
+                "--convergence",
+                "10",
+                "--camera1-flip-y",
+                "--camera2-roll",
+                "1",
+                "--metrics-json",
+                "m.json",
+                "--stats",
+            ];
+            let args = [&["anaglyph_robot"][..], mode, &options].concat();
+            ignored_options(&command.clone().get_matches_from(args), honoured)
+        };
+        assert_eq!(
+            ignored(&["--cpu-render"], &[SETUP_OPTIONS, CPU_RENDER_OPTIONS]),
+            ["--camera2-roll", "--convergence", "--stats"]
+        );
+        assert_eq!(
+            ignored(
+                &["--headless", "--output-pipe", "-"],
+                &[SETUP_OPTIONS, COMPOSITOR_OPTIONS, HEADLESS_OPTIONS]
+            ),
+            ["--stats"]
+        );
+    }
+
+    #[test]
+    fn only_the_window_records() {
+        for mode in [
+            &["--cpu-render"][..],
+            &["--headless"],
+            &["--once", "--output", "a.ppm"],
+        ] {
+            assert!(Cli::try_parse_from([&["anaglyph_robot"][..], mode].concat()).is_ok());
+            let args = [&["anaglyph_robot", "--record-stereo", "a.mkv"][..], mode].concat();
+            assert!(Cli::try_parse_from(args).is_err(), "{mode:?} records");
+        }
+    }
+
+    #[test]
+    fn the_vignette_is_a_finite_number() {
+        for value in ["NaN", "inf", "-inf"] {
+            let args = ["anaglyph_robot", "--vignette-radius", value];
+            assert!(Cli::try_parse_from(args).is_err(), "accepted {value}");
+        }
+        let args = Cli::parse_from(["anaglyph_robot", "--vignette", "0.5"]);
+        assert_eq!(args.vignette, 0.5);
+    }
+
+    #[test]
+    fn window_title_names_the_mode() {
+        let args = Cli::parse_from(["anaglyph_robot", "/dev/video0", "/dev/video2"]);
+        assert_eq!(
+            window_title(&args, Mode::Disparity, ColorScheme::RedCyan),
+            "disparity: /dev/video0 + /dev/video2 [red-cyan]"
+        );
+    }
 }
@@ -0,0 +1,224 @@
+//! Machine-readable frame pacing statistics for `--metrics-json`.
+
+use serde::Serialize;
+use std::{path::Path, time::Duration, time::Instant};
+
+/// Counters updated by a capture thread for every buffer it dequeues.
+#[derive(Debug)]
+pub struct CaptureStats {
+    started: Instant,
+    frames: u64,
+    dropped: u64,
+    decode_ms: Reservoir,
+    last_sequence: Option<u32>,
+}
+
+impl Default for CaptureStats {
+    fn default() -> Self {
+        CaptureStats {
+            started: Instant::now(),
+            frames: 0,
+            dropped: 0,
+            decode_ms: Reservoir::default(),
+            last_sequence: None,
+        }
+    }
+}
+
+impl CaptureStats {
+    /// Records a dequeued buffer; gaps in the driver's sequence numbers count as dropped frames.
+    pub fn buffer(&mut self, sequence: u32) {
+        if let Some(last) = self.last_sequence {
+            self.dropped += u64::from(sequence.wrapping_sub(last).saturating_sub(1));
+        }
+        self.last_sequence = Some(sequence);
+    }
+
+    /// Records a published frame that didn't need decoding.
+    pub fn published(&mut self) {
+        self.frames += 1;
+    }
+
+    /// Records a successfully decoded and published frame.
+    pub fn decoded(&mut self, decode_time: Duration) {
+        self.published();
+        self.decode_ms.record(decode_time.as_secs_f32() * 1000.0);
+    }
+
+    /// Records a buffer that couldn't be decoded and was thrown away.
+    pub fn failed(&mut self) {
+        self.dropped += 1;
+    }
+
+    /// Starts over, e.g. when the capture is restarted and sequence numbers reset.
+    pub fn restart(&mut self) {
+        self.last_sequence = None;
+    }
+
+    fn report(&self, path: &str) -> CameraReport {
+        CameraReport {
+            path: path.to_owned(),
+            fps: self.frames as f32 / self.started.elapsed().as_secs_f32(),
+            frames: self.frames,
+            dropped_frames: self.dropped,
+            decode_ms: Percentiles::of(&self.decode_ms.samples),
+        }
+    }
+}
+
+/// Samples kept for the percentiles of a process' whole run, however long it runs.
+const RESERVOIR_SIZE: usize = 10_000;
+
+/// A uniform random sample of at most [`RESERVOIR_SIZE`] of all the values recorded, so
+/// percentiles over days of frames take constant memory.
+#[derive(Debug)]
+pub struct Reservoir {
+    samples: Vec<f32>,
+    seen: u64,
+    /// xorshift64 state, good enough for picking which samples to replace.
+    random: u64,
+}
+
+impl Default for Reservoir {
+    fn default() -> Self {
+        Reservoir {
+            samples: Vec::new(),
+            seen: 0,
+            random: 0x9E37_79B9_7F4A_7C15,
+        }
+    }
+}
+
+impl Reservoir {
+    pub fn record(&mut self, value: f32) {
+        self.seen += 1;
+        if self.samples.len() < RESERVOIR_SIZE {
+            self.samples.push(value);
+            return;
+        }
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 7;
+        self.random ^= self.random << 17;
+        // replaces one with the chance that keeps every value seen equally likely to be kept
+        let index = self.random % self.seen;
+        if let Some(sample) = self.samples.get_mut(index as usize) {
+            *sample = value;
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Percentiles {
+    p50: f32,
+    p90: f32,
+    p99: f32,
+    max: f32,
+}
+
+impl Percentiles {
+    fn of(samples: &[f32]) -> Option<Self> {
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f32::total_cmp);
+        let at = |p: f32| sorted[((sorted.len() - 1) as f32 * p).round() as usize];
+        (!sorted.is_empty()).then(|| Percentiles {
+            p50: at(0.5),
+            p90: at(0.9),
+            p99: at(0.99),
+            max: at(1.0),
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct CameraReport {
+    path: String,
+    fps: f32,
+    frames: u64,
+    dropped_frames: u64,
+    decode_ms: Option<Percentiles>,
+}
+
+#[derive(Debug, Serialize)]
+struct Report {
+    cameras: Vec<CameraReport>,
+    render_ms: Option<Percentiles>,
+}
+
+/// Time from capture to presentation of each camera's frames, summarized once a second.
+pub struct Latency {
+    samples: [Vec<f32>; 2],
+    since: Instant,
+}
+
+impl Latency {
+    pub fn new() -> Self {
+        Latency {
+            samples: Default::default(),
+            since: Instant::now(),
+        }
+    }
+
+    /// Records the latency of a frame of camera1 (0) or camera2 (1).
+    pub fn record(&mut self, camera: usize, latency: Duration) {
+        self.samples[camera].push(latency.as_secs_f32() * 1000.0);
+    }
+
+    /// Describes the latencies recorded since the last summary, once a second has passed.
+    pub fn summary(&mut self) -> Option<String> {
+        if self.since.elapsed() < Duration::from_secs(1) {
+            return None;
+        }
+        self.since = Instant::now();
+        let cameras: Vec<_> = self
+            .samples
+            .iter_mut()
+            .enumerate()
+            .map(|(i, samples)| {
+                let summary = match Percentiles::of(samples) {
+                    Some(p) => format!("p50 {:.1} ms, p99 {:.1} ms", p.p50, p.p99),
+                    None => "no frames".to_owned(),
+                };
+                samples.clear();
+                format!("camera{} {summary}", i + 1)
+            })
+            .collect();
+        Some(format!("capture to present: {}", cameras.join("; ")))
+    }
+}
+
+/// Writes the statistics of all cameras and the render loop to `path` as JSON.
+pub fn write_json(
+    path: &Path,
+    cameras: &[(&str, &CaptureStats)],
+    render_ms: &Reservoir,
+) -> anyhow::Result<()> {
+    let report = Report {
+        cameras: cameras
+            .iter()
+            .map(|(path, stats)| stats.report(path))
+            .collect(),
+        render_ms: Percentiles::of(&render_ms.samples),
+    };
+    std::fs::write(path, serde_json::to_string_pretty(&report)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reservoirs_stay_bounded_and_representative() {
+        let mut reservoir = Reservoir::default();
+        for value in 0..10 * RESERVOIR_SIZE {
+            reservoir.record(value as f32);
+        }
+        assert_eq!(reservoir.samples.len(), RESERVOIR_SIZE);
+        let median = Percentiles::of(&reservoir.samples).unwrap().p50;
+        let expected = 5.0 * RESERVOIR_SIZE as f32;
+        assert!(
+            (median - expected).abs() < 0.05 * expected,
+            "median {median}"
+        );
+    }
+}
@@ -0,0 +1,48 @@
+//! Destinations other than the window that every composited frame is also sent to.
+
+/// Something that takes the composited frames, e.g. a V4L2 output device.
+pub trait OutputSink {
+    /// Names the sink in messages, e.g. by its path.
+    fn name(&self) -> String;
+
+    /// Takes one frame of RGBA with the bottom row first, as OpenGL reads it back.
+    fn write(&mut self, rgba: &[u8], width: u32, height: u32) -> anyhow::Result<()>;
+}
+
+/// Turns RGBA with the bottom row first, as the sinks get it, into RGB with the top row first,
+/// as files and other processes expect it.
+pub fn top_down_rgb(rgba: &[u8], width: u32) -> Vec<u8> {
+    let mut rgb = Vec::with_capacity(rgba.len() / 4 * 3);
+    for row in rgba.chunks_exact(width as usize * 4).rev() {
+        for pixel in row.chunks_exact(4) {
+            rgb.extend_from_slice(&pixel[..3]);
+        }
+    }
+    rgb
+}
+
+/// Writes a frame to every sink, dropping those that fail so one broken output doesn't stop
+/// the others.
+pub fn write_all(sinks: &mut Vec<Box<dyn OutputSink>>, rgba: &[u8], width: u32, height: u32) {
+    sinks.retain_mut(|sink| match sink.write(rgba, width, height) {
+        Ok(()) => true,
+        Err(error) => {
+            eprintln!("\nstopped writing to {}: {error:#}", sink.name());
+            false
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgb_starts_at_the_top_and_drops_alpha() {
+        let rgba = [1, 2, 3, 255, 4, 5, 6, 255, 7, 8, 9, 0, 10, 11, 12, 0];
+        assert_eq!(
+            top_down_rgb(&rgba, 2),
+            [7, 8, 9, 10, 11, 12, 1, 2, 3, 4, 5, 6]
+        );
+    }
+}
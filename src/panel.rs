@@ -0,0 +1,214 @@
+//! An egui settings window drawn over the anaglyph, toggled with Tab, a read-only HUD of
+//! the main settings, toggled with H, and the `--self-test` and `--verify-glasses` screens.
+
+use clap::ValueEnum;
+use egui_glium::EguiGlium;
+use glium::{backend::glutin::Display, glutin::surface::WindowSurface};
+use winit::{event::WindowEvent, event_loop::EventLoopWindowTarget, window::Window};
+
+use crate::{
+    scheme::{ChannelMask, ColorScheme},
+    settings::Settings,
+};
+
+pub struct Panel {
+    egui: EguiGlium,
+    pub visible: bool,
+    pub hud: bool,
+    /// Covers everything with LEFT and RIGHT in the colors of the lenses they're meant for.
+    pub self_test: bool,
+    /// Covers everything with each lens color in turn while asking which eye sees it, holding
+    /// the answers so far.
+    pub verify_glasses: Option<Vec<Lens>>,
+    export_path: String,
+}
+
+/// A lens of the glasses, see [`Panel::verify_glasses`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lens {
+    Left,
+    Right,
+}
+
+impl Panel {
+    pub fn new(
+        display: &Display<WindowSurface>,
+        window: &Window,
+        event_loop: &EventLoopWindowTarget<()>,
+    ) -> Self {
+        Panel {
+            egui: EguiGlium::new(egui::ViewportId::ROOT, display, window, event_loop),
+            visible: false,
+            hud: false,
+            self_test: false,
+            verify_glasses: None,
+            export_path: "anaglyph_robot.args".to_owned(),
+        }
+    }
+
+    /// Passes the event to egui while the panel is shown; true if egui used it.
+    pub fn on_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        self.visible && self.egui.on_event(window, event).consumed
+    }
+
+    /// Draws the glasses check or the self-test, or the panel and the HUD if they're shown,
+    /// applying changes to `settings` right away.
+    ///
+    /// `camera2_size` bounds the offset sliders, `fps` is shown in the HUD.
+    pub fn draw(
+        &mut self,
+        display: &Display<WindowSurface>,
+        window: &Window,
+        target: &mut glium::Frame,
+        settings: &mut Settings,
+        camera2_size: (u32, u32),
+        fps: f32,
+    ) {
+        let (visible, hud, self_test) = (self.visible, self.hud, self.self_test);
+        let asked = self.verify_glasses.as_ref().map(Vec::len);
+        if !visible && !hud && !self_test && asked.is_none() {
+            return;
+        }
+        let export_path = &mut self.export_path;
+        self.egui.run(window, |ctx| {
+            if let Some(asked) = asked {
+                draw_glasses_question(ctx, settings.color_scheme, asked);
+                return;
+            }
+            if self_test {
+                draw_self_test(ctx, settings.color_scheme);
+                return;
+            }
+            if hud {
+                let text = format!(
+                    "{}\nconvergence {:.1}  depth bias {:.1}\ncamera2 offset {} {}\n\
+                     gamma {:.2}\n{fps:.0} fps",
+                    settings.color_scheme,
+                    settings.convergence,
+                    settings.depth_bias,
+                    settings.camera2_offset[0],
+                    settings.camera2_offset[1],
+                    settings.gamma,
+                );
+                egui::Area::new(egui::Id::new("hud"))
+                    .anchor(egui::Align2::LEFT_TOP, [8.0, 8.0])
+                    .interactable(false)
+                    .show(ctx, |ui| {
+                        egui::Frame::none()
+                            .fill(egui::Color32::from_black_alpha(160))
+                            .inner_margin(6.0)
+                            .show(ui, |ui| {
+                                ui.label(
+                                    egui::RichText::new(text)
+                                        .monospace()
+                                        .color(egui::Color32::WHITE),
+                                )
+                            });
+                    });
+            }
+            if !visible {
+                return;
+            }
+            egui::Window::new("Settings").show(ctx, |ui| {
+                let (w, h) = (camera2_size.0 as i32 - 1, camera2_size.1 as i32 - 1);
+                ui.add(
+                    egui::Slider::new(&mut settings.convergence, -200.0..=200.0)
+                        .text("convergence"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut settings.depth_bias, -200.0..=200.0).text("depth bias"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut settings.camera2_offset[0], -w..=w).text("camera2 x"),
+                );
+                ui.add(
+                    egui::Slider::new(&mut settings.camera2_offset[1], -h..=h).text("camera2 y"),
+                );
+                ui.add(egui::Slider::new(&mut settings.crosstalk, 0.0..=1.0).text("crosstalk"));
+                ui.add(egui::Slider::new(&mut settings.comfort, 0.0..=1.0).text("comfort"));
+                ui.add(egui::Slider::new(&mut settings.brightness, 0.0..=4.0).text("brightness"));
+                ui.add(egui::Slider::new(&mut settings.gamma, 0.2..=5.0).text("gamma"));
+                let [eye1, eye2] = &mut settings.eye_gains;
+                ui.add(egui::Slider::new(eye1, 0.0..=2.0).text("eye1 gain"));
+                ui.add(egui::Slider::new(eye2, 0.0..=2.0).text("eye2 gain"));
+                egui::ComboBox::from_label("color scheme")
+                    .selected_text(settings.color_scheme.to_string())
+                    .show_ui(ui, |ui| {
+                        for &scheme in ColorScheme::value_variants() {
+                            ui.selectable_value(
+                                &mut settings.color_scheme,
+                                scheme,
+                                scheme.to_string(),
+                            );
+                        }
+                    });
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.text_edit_singleline(export_path);
+                    if ui.button("Export").clicked() {
+                        match std::fs::write(&*export_path, settings.flags() + "\n") {
+                            Ok(()) => println!("\nWrote settings to {export_path}"),
+                            Err(error) => eprintln!("\nfailed to write {export_path}: {error}"),
+                        }
+                    }
+                });
+            });
+        });
+        self.egui.paint(display, target);
+    }
+}
+
+/// The color a lens lets through, at full brightness.
+fn lens_color(mask: ChannelMask) -> egui::Color32 {
+    let [r, g, b] = mask.factors().map(|on| (on * 255.0) as u8);
+    egui::Color32::from_rgb(r, g, b)
+}
+
+/// Fills the screen with the color of the scheme's lens at index `asked`.
+fn draw_glasses_question(ctx: &egui::Context, color_scheme: ColorScheme, asked: usize) {
+    let color = lens_color(color_scheme.masks()[asked]);
+    egui::CentralPanel::default()
+        .frame(egui::Frame::none().fill(color))
+        .show(ctx, |ui| {
+            ui.centered_and_justified(|ui| {
+                ui.label(
+                    egui::RichText::new(
+                        "Close one eye, then the other: which eye sees this color brightly?\n\
+                         Press L for left or R for right.",
+                    )
+                    .size(32.0)
+                    .color(egui::Color32::BLACK),
+                )
+            });
+        });
+}
+
+/// Each word is only visible through its lens, the white instructions through both.
+fn draw_self_test(ctx: &egui::Context, color_scheme: ColorScheme) {
+    let [left, right] = color_scheme.masks().map(lens_color);
+    egui::CentralPanel::default()
+        .frame(egui::Frame::none().fill(egui::Color32::BLACK))
+        .show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.add_space(40.0);
+                ui.label(
+                    egui::RichText::new(format!(
+                        "If you can read LEFT only through your left lens and RIGHT only \
+                         through your right lens, your glasses and the {color_scheme} scheme \
+                         are correct.\nPress any key to start."
+                    ))
+                    .size(24.0)
+                    .color(egui::Color32::WHITE),
+                );
+            });
+            ui.columns(2, |columns| {
+                for (column, (text, color)) in
+                    columns.iter_mut().zip([("LEFT", left), ("RIGHT", right)])
+                {
+                    column.centered_and_justified(|ui| {
+                        ui.label(egui::RichText::new(text).size(160.0).strong().color(color))
+                    });
+                }
+            });
+        });
+}
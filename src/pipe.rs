@@ -0,0 +1,147 @@
+//! `--output-pipe`: writes the composited frames to stdout or a named pipe, for feeding them
+//! into ffmpeg or another process in a shell pipeline.
+//!
+//! With `--output-pipe-format rgb`, every frame is width × height × 3 bytes of 8-bit RGB, rows
+//! top to bottom and pixels left to right, with no header or padding between frames. With
+//! `mjpeg`, every frame is a complete baseline JPEG, from SOI to EOI, written right after the
+//! previous one, at `--output-quality` if given. The size is the one of the first frame, which
+//! is printed to stderr; it stays fixed, so give --output-width and --output-height to keep
+//! resizing the window from ending the output.
+
+use anyhow::Context;
+use std::{
+    fs::File,
+    io::Write,
+    os::fd::FromRawFd,
+    path::Path,
+    process::{Child, Command, Stdio},
+};
+
+use crate::output::{self, OutputSink};
+
+/// How frames are encoded into the pipe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PipeFormat {
+    /// Raw packed 8-bit RGB
+    Rgb,
+    /// Concatenated JPEGs, encoded by `ffmpeg`
+    Mjpeg,
+}
+
+pub struct Pipe {
+    name: String,
+    format: PipeFormat,
+    /// JPEG quality from 0 to 100 for MJPEG, or ffmpeg's default.
+    quality: Option<u8>,
+    /// Where the frames go, until it's handed to ffmpeg for MJPEG.
+    out: Option<File>,
+    /// The size of the first frame and, for MJPEG, the ffmpeg encoding into `out`.
+    started: Option<((u32, u32), Option<Child>)>,
+}
+
+impl Pipe {
+    /// Opens `path` for writing, or stdout for `-`. Opening a named pipe waits for a reader.
+    ///
+    /// Writing to stdout moves everything else printed there to stderr, so messages don't end
+    /// up in the stream.
+    pub fn open(path: &Path, format: PipeFormat, quality: Option<u8>) -> anyhow::Result<Self> {
+        let (out, name) = if path == Path::new("-") {
+            // SAFETY: duplicating and replacing the standard descriptors, which stay open
+            let out = unsafe {
+                let fd = libc::dup(libc::STDOUT_FILENO);
+                anyhow::ensure!(
+                    fd >= 0 && libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) >= 0,
+                    "Couldn't take over stdout: {}",
+                    std::io::Error::last_os_error()
+                );
+                File::from_raw_fd(fd)
+            };
+            (out, "stdout".to_owned())
+        } else {
+            let out = File::options()
+                .write(true)
+                .open(path)
+                .with_context(|| format!("Couldn't open {}", path.display()))?;
+            (out, path.display().to_string())
+        };
+        Ok(Pipe {
+            name,
+            format,
+            quality,
+            out: Some(out),
+            started: None,
+        })
+    }
+}
+
+impl OutputSink for Pipe {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn write(&mut self, rgba: &[u8], width: u32, height: u32) -> anyhow::Result<()> {
+        let ((started_width, started_height), ffmpeg) = match &mut self.started {
+            Some(started) => started,
+            None => {
+                let ffmpeg = match self.format {
+                    PipeFormat::Rgb => None,
+                    PipeFormat::Mjpeg => {
+                        let out = self.out.take().unwrap();
+                        Some(spawn(out, width, height, self.quality)?)
+                    }
+                };
+                let kind = match self.format {
+                    PipeFormat::Rgb => "RGB",
+                    PipeFormat::Mjpeg => "MJPEG",
+                };
+                eprintln!("\nWriting {width}x{height} {kind} frames to {}", self.name);
+                self.started.insert(((width, height), ffmpeg))
+            }
+        };
+        anyhow::ensure!(
+            (*started_width, *started_height) == (width, height),
+            "the frames changed from {started_width}x{started_height} to {width}x{height}"
+        );
+        let rgb = output::top_down_rgb(rgba, width);
+        let out: &mut dyn Write = match ffmpeg {
+            Some(ffmpeg) => ffmpeg
+                .stdin
+                .as_mut()
+                .expect("ffmpeg is spawned with piped stdin"),
+            None => self.out.as_mut().unwrap(),
+        };
+        out.write_all(&rgb)?;
+        Ok(())
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        if let Some((_, Some(mut ffmpeg))) = self.started.take() {
+            // closing stdin lets ffmpeg encode what it still has
+            drop(ffmpeg.stdin.take());
+            let _ = ffmpeg.wait();
+        }
+    }
+}
+
+/// Starts ffmpeg encoding raw RGB frames of the given size into JPEGs written to `out`.
+fn spawn(out: File, width: u32, height: u32, quality: Option<u8>) -> anyhow::Result<Child> {
+    let mut ffmpeg = Command::new("ffmpeg");
+    ffmpeg
+        .args(["-loglevel", "error"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24", "-s"])
+        .arg(format!("{width}x{height}"))
+        .args(["-i", "-", "-c:v", "mjpeg", "-pix_fmt", "yuvj420p"]);
+    if let Some(quality) = quality {
+        // ffmpeg's quantizer scale goes from 2, the best, to 31
+        let scale = 31 - u32::from(quality.min(100)) * 29 / 100;
+        ffmpeg.arg("-q:v").arg(scale.to_string());
+    }
+    ffmpeg
+        .args(["-f", "mjpeg", "-"])
+        .stdin(Stdio::piped())
+        .stdout(out)
+        .spawn()
+        .context("Couldn't start ffmpeg")
+}
@@ -0,0 +1,189 @@
+//! `--record-stereo`: both cameras' decoded frames side by side in a lossless video, with their
+//! capture times next to it, for depth processing offline.
+
+use anyhow::Context;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    sync::mpsc::{self, SyncSender, TrySendError},
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use crate::{
+    camera::{Frame, PixelFormat},
+    color,
+};
+
+/// Pairs waiting for ffmpeg before new ones are dropped.
+const QUEUE: usize = 4;
+
+/// Both cameras' frames.
+type Pair = [Frame; 2];
+
+pub struct StereoRecorder {
+    sender: Option<SyncSender<Pair>>,
+    thread: Option<JoinHandle<()>>,
+    /// Capture times of the last pair sent, so every frame is recorded once.
+    recorded: [Option<Instant>; 2],
+    dropped: u32,
+}
+
+impl StereoRecorder {
+    /// Starts recording to `path`, with the capture times in `path` plus `.csv`; YCbCr frames
+    /// are converted to RGB first.
+    pub fn start(path: &Path) -> anyhow::Result<Self> {
+        let mut csv_path = path.as_os_str().to_owned();
+        csv_path.push(".csv");
+        let csv_path = PathBuf::from(csv_path);
+        let mut timestamps = BufWriter::new(
+            File::create(&csv_path)
+                .with_context(|| format!("Couldn't create {}", csv_path.display()))?,
+        );
+        writeln!(timestamps, "pair,camera1_ms,camera2_ms")?;
+        println!("Recording both cameras to {}", path.display());
+        let (sender, receiver) = mpsc::sync_channel::<Pair>(QUEUE);
+        let path = path.to_owned();
+        let thread = thread::Builder::new()
+            .name("record-stereo".to_owned())
+            .spawn(move || {
+                let mut ffmpeg: Option<(Child, (u32, u32))> = None;
+                let start = Instant::now();
+                for (index, pair) in receiver.into_iter().enumerate() {
+                    if let Err(error) =
+                        write(&path, &mut ffmpeg, &mut timestamps, index, pair, start)
+                    {
+                        eprintln!("\nstopped recording to {}: {error:#}", path.display());
+                        break;
+                    }
+                }
+                if let Some((mut ffmpeg, _)) = ffmpeg {
+                    // closing stdin lets ffmpeg finish the file
+                    drop(ffmpeg.stdin.take());
+                    if let Err(error) = ffmpeg.wait() {
+                        eprintln!("\nffmpeg didn't finish {}: {error}", path.display());
+                    }
+                }
+                if let Err(error) = timestamps.flush() {
+                    eprintln!("\nfailed to write {}: {error}", csv_path.display());
+                }
+            })?;
+        Ok(StereoRecorder {
+            sender: Some(sender),
+            thread: Some(thread),
+            recorded: [None; 2],
+            dropped: 0,
+        })
+    }
+
+    /// Records the cameras' current frames if both are new since the last pair; `frames`
+    /// are read only then.
+    pub fn offer(&mut self, captured: [Option<Instant>; 2], frames: impl FnOnce() -> Pair) {
+        if captured.contains(&None) || (0..2).any(|index| self.recorded[index] == captured[index]) {
+            return;
+        }
+        self.recorded = captured;
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        match sender.try_send(frames()) {
+            Ok(()) => (),
+            Err(TrySendError::Full(_)) => self.dropped += 1,
+            // the thread stopped after an error it already reported
+            Err(TrySendError::Disconnected(_)) => self.sender = None,
+        }
+    }
+
+    /// Waits for everything queued to be written.
+    pub fn finish(mut self) {
+        drop(self.sender.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+        if self.dropped > 0 {
+            eprintln!(
+                "WARNING: dropped {} stereo pairs that couldn't be encoded fast enough",
+                self.dropped
+            );
+        }
+    }
+}
+
+/// Writes one pair side by side, starting ffmpeg on the first one.
+fn write(
+    path: &Path,
+    ffmpeg: &mut Option<(Child, (u32, u32))>,
+    timestamps: &mut impl Write,
+    index: usize,
+    pair: Pair,
+    start: Instant,
+) -> anyhow::Result<()> {
+    let [mut frame1, mut frame2] = pair;
+    let (width, height) = (frame1.width, frame1.height);
+    anyhow::ensure!(
+        (frame2.width, frame2.height) == (width, height),
+        "the cameras' frames differ in size, {width}x{height} and {}x{}",
+        frame2.width,
+        frame2.height
+    );
+    let (ffmpeg, size) = match ffmpeg {
+        Some(ffmpeg) => ffmpeg,
+        None => ffmpeg.insert((spawn(path, width, height)?, (width, height))),
+    };
+    anyhow::ensure!(
+        *size == (width, height),
+        "the cameras switched from {}x{} to {width}x{height}",
+        size.0,
+        size.1
+    );
+    let row = width as usize * 3;
+    let frames = [&mut frame1, &mut frame2];
+    for frame in frames {
+        anyhow::ensure!(
+            !matches!(frame.pixel_format, PixelFormat::Bayer(_)),
+            "raw Bayer frames can't be recorded"
+        );
+        if frame.pixel_format == PixelFormat::Ycbcr {
+            for pixel in frame.data.chunks_exact_mut(3) {
+                let rgb = color::ycbcr_to_rgb([pixel[0], pixel[1], pixel[2]].map(f32::from));
+                pixel.copy_from_slice(&rgb.map(|channel| channel.clamp(0.0, 255.0) as u8));
+            }
+        }
+    }
+    let mut side_by_side = Vec::with_capacity(frame1.data.len() * 2);
+    for (left, right) in frame1
+        .data
+        .chunks_exact(row)
+        .zip(frame2.data.chunks_exact(row))
+    {
+        side_by_side.extend_from_slice(left);
+        side_by_side.extend_from_slice(right);
+    }
+    ffmpeg
+        .stdin
+        .as_mut()
+        .expect("ffmpeg is spawned with piped stdin")
+        .write_all(&side_by_side)
+        .context("ffmpeg stopped taking frames")?;
+    let [camera1, camera2] = [frame1.timestamp, frame2.timestamp]
+        .map(|captured| captured.saturating_duration_since(start).as_secs_f64() * 1000.0);
+    writeln!(timestamps, "{index},{camera1:.3},{camera2:.3}")?;
+    Ok(())
+}
+
+/// Starts ffmpeg encoding raw RGB of twice `width` losslessly with FFV1, timestamped as the
+/// pairs arrive.
+fn spawn(path: &Path, width: u32, height: u32) -> anyhow::Result<Child> {
+    Command::new("ffmpeg")
+        .args(["-loglevel", "error", "-y"])
+        .args(["-use_wallclock_as_timestamps", "1"])
+        .args(["-f", "rawvideo", "-pix_fmt", "rgb24", "-s"])
+        .arg(format!("{}x{height}", width * 2))
+        .args(["-i", "-", "-vsync", "0", "-c:v", "ffv1"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .spawn()
+        .context("Couldn't start ffmpeg")
+}
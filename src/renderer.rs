@@ -0,0 +1,220 @@
+//! [`AnaglyphRenderer`], the offscreen compositor behind `--once` and `--headless`, which can
+//! also be handed frames from elsewhere instead of reading them from cameras.
+
+use anyhow::Context;
+use glium::Surface;
+use std::{
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    build_camera_program, camera::Bayer, camera_shader_opts, camera_uniforms, clahe, display,
+    eye_masks, eyes, frame_texture, quad, settings::Settings, shaders::ShaderOpts, Camera, Cli,
+    Frame, PixelFormat, Vertex,
+};
+
+/// What [`AnaglyphRenderer::on_frame`] is told about each composited anaglyph.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameMetadata {
+    /// How long uploading, drawing and reading back the anaglyph took.
+    pub render_time: Duration,
+    /// How old camera1's and camera2's frames were once it was done, from their capture or
+    /// from being set.
+    pub staleness: [Duration; 2],
+    /// Camera1's and camera2's frames were already in the previous anaglyph.
+    pub repeated: [bool; 2],
+}
+
+type FrameHook<'a> = Box<dyn FnMut(&FrameMetadata) + 'a>;
+
+/// Draws two eyes' frames into an anaglyph offscreen, with the color scheme, alignment and
+/// output size of the `Cli` it's created with.
+pub struct AnaglyphRenderer<'a> {
+    args: &'a Cli,
+    context: Rc<glium::backend::Context>,
+    quad: (glium::VertexBuffer<Vertex>, glium::IndexBuffer<u16>),
+    /// Each camera's program and the frames' Bayer pattern it was built for, built on first use
+    /// and again when the pattern changes.
+    programs: [Option<(Option<Bayer>, glium::Program)>; 2],
+    /// For --clahe.
+    clahe: Option<glium::Program>,
+    /// Stand-ins for camera1 and camera2 showing the frames set with
+    /// [`AnaglyphRenderer::set_left_frame`] and [`AnaglyphRenderer::set_right_frame`].
+    injected: [Option<Camera>; 2],
+    on_frame: Option<FrameHook<'a>>,
+    /// The capture times of the previous anaglyph's frames, for [`FrameMetadata::repeated`].
+    previous: [Option<Instant>; 2],
+}
+
+impl<'a> AnaglyphRenderer<'a> {
+    /// A renderer on the first EGL device that works.
+    pub fn new(args: &'a Cli) -> anyhow::Result<Self> {
+        let context = display::headless().context("No EGL device to render offscreen on")?;
+        args.texture_format.check(&context)?;
+        let clahe = args.clahe.map(|_| clahe::program(&context)).transpose()?;
+        Ok(AnaglyphRenderer {
+            args,
+            quad: quad(&context),
+            context,
+            programs: [None, None],
+            clahe,
+            injected: [None, None],
+            on_frame: None,
+            previous: [None; 2],
+        })
+    }
+
+    /// Has every composite call `hook` once the anaglyph has been read back, e.g. for
+    /// telemetry. Without one, no metadata is gathered.
+    pub fn on_frame(&mut self, hook: impl FnMut(&FrameMetadata) + 'a) {
+        self.on_frame = Some(Box::new(hook));
+    }
+
+    /// Shows `rgb` in camera1's place from the next [`AnaglyphRenderer::render`] on.
+    ///
+    /// The frame is interleaved RGB, three bytes per pixel with the rows top to bottom and no
+    /// padding. Its size may differ from the previous frame's and from the other eye's.
+    pub fn set_left_frame(&mut self, rgb: &[u8], width: u32, height: u32) -> anyhow::Result<()> {
+        self.set_frame(0, rgb, width, height)
+    }
+
+    /// Shows `rgb` in camera2's place, like [`AnaglyphRenderer::set_left_frame`].
+    pub fn set_right_frame(&mut self, rgb: &[u8], width: u32, height: u32) -> anyhow::Result<()> {
+        self.set_frame(1, rgb, width, height)
+    }
+
+    fn set_frame(
+        &mut self,
+        index: usize,
+        rgb: &[u8],
+        width: u32,
+        height: u32,
+    ) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            rgb.len() == width as usize * height as usize * 3,
+            "a {width}x{height} RGB frame has {} bytes, got {}",
+            width as usize * height as usize * 3,
+            rgb.len()
+        );
+        self.injected[index] = Some(Camera::still(rgb.to_vec(), width, height));
+        Ok(())
+    }
+
+    /// Composites the frames last set for both eyes, see [`AnaglyphRenderer::composite`].
+    pub fn render(&mut self) -> anyhow::Result<(Vec<u8>, (u32, u32))> {
+        let [Some(mut left), Some(mut right)] = std::mem::take(&mut self.injected) else {
+            anyhow::bail!("both eyes need a frame before rendering");
+        };
+        let frames = [left.frame().clone(), right.frame().clone()];
+        let rendered = self.composite([&left, &right], &frames);
+        self.injected = [Some(left), Some(right)];
+        rendered
+    }
+
+    /// Renders the anaglyph at the size of camera1's frame or --output-width and
+    /// --output-height, returning it as RGBA with the bottom row first and its size.
+    ///
+    /// Crosstalk cancellation and the comfort filter aren't applied.
+    pub(crate) fn composite(
+        &mut self,
+        cameras: [&Camera; 2],
+        frames: &[Frame; 2],
+    ) -> anyhow::Result<(Vec<u8>, (u32, u32))> {
+        for (index, camera) in cameras.iter().enumerate() {
+            if !matches!(&self.programs[index], Some((bayer, _)) if *bayer == camera.bayer) {
+                let opts = ShaderOpts {
+                    transparent: false,
+                    linear: false,
+                    ..camera_shader_opts(self.args, index, camera.bayer)
+                };
+                let program = build_camera_program(&self.context, &opts)?;
+                self.programs[index] = Some((camera.bayer, program));
+            }
+        }
+        // building the programs is left out of FrameMetadata::render_time
+        let start = Instant::now();
+        let (args, context) = (self.args, &self.context);
+        let (width, height) = args
+            .output_size()
+            .unwrap_or((frames[0].width, frames[0].height));
+        let (vertex_buffer, index_buffer) = &self.quad;
+        let target = glium::texture::Texture2d::empty(context, width, height)?;
+        let mut framebuffer = glium::framebuffer::SimpleFrameBuffer::new(context, &target)?;
+        framebuffer.clear_color(0.0, 0.0, 0.0, 1.0);
+
+        let settings = Settings::new(args);
+        let eyes = eyes(args, &settings, cameras, [1.0; 2], (width, height));
+        let masks = eye_masks(args, args.color_scheme);
+        for index in 0..2 {
+            let frame = &frames[index];
+            let mut texture = frame_texture(context, frame, args.texture_format)
+                .with_context(|| format!("camera{}'s frame is incomplete", index + 1))?;
+            if let (Some(program), Some(clip_limit)) = (&self.clahe, args.clahe) {
+                let gain_map = clahe::gain_map(frame, clip_limit);
+                let ycbcr = frame.pixel_format == PixelFormat::Ycbcr;
+                let quad = (vertex_buffer, index_buffer);
+                texture = clahe::apply(context, program, quad, &texture, gain_map, ycbcr)?;
+            }
+            let params = glium::DrawParameters {
+                color_mask: masks[index].color_mask(),
+                ..Default::default()
+            };
+            let uniforms = camera_uniforms(&texture, eyes[index], args.edge);
+            framebuffer.draw(
+                vertex_buffer,
+                index_buffer,
+                &self.programs[index].as_ref().unwrap().1,
+                &uniforms,
+                &params,
+            )?;
+        }
+        let image: glium::texture::RawImage2d<u8> = target.read();
+        if let Some(hook) = &mut self.on_frame {
+            let done = Instant::now();
+            let captured = [0, 1].map(|index| frames[index].timestamp);
+            hook(&FrameMetadata {
+                render_time: done - start,
+                staleness: captured.map(|captured| done.saturating_duration_since(captured)),
+                repeated: [0, 1].map(|index| self.previous[index] == Some(captured[index])),
+            });
+            self.previous = captured.map(Some);
+        }
+        Ok((image.data.into_owned(), (width, height)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn injected_frames_can_change_size_and_are_reported() {
+        let args = Cli::parse_from(["anaglyph_robot"]);
+        let Ok(mut renderer) = AnaglyphRenderer::new(&args) else {
+            eprintln!("skipping, no EGL device to render on");
+            return;
+        };
+        let composited = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let hook = Rc::clone(&composited);
+        renderer.on_frame(move |frame| hook.borrow_mut().push(frame.repeated));
+        assert!(renderer.render().is_err());
+        assert!(renderer.set_left_frame(&[0; 5], 1, 2).is_err());
+        for (width, height) in [(4, 2), (2, 6)] {
+            let red = [255, 0, 0].repeat(width * height);
+            let cyan = [0, 255, 255].repeat(width * height);
+            let (width, height) = (width as u32, height as u32);
+            renderer.set_left_frame(&red, width, height).unwrap();
+            renderer.set_right_frame(&cyan, width, height).unwrap();
+            let (rgba, size) = renderer.render().unwrap();
+            assert_eq!(size, (width, height));
+            assert_eq!(&rgba[..4], [255, 255, 255, 255]);
+        }
+        renderer.render().unwrap();
+        assert_eq!(
+            *composited.borrow(),
+            [[false, false], [false, false], [true, true]]
+        );
+    }
+}
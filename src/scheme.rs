@@ -0,0 +1,117 @@
+use std::fmt;
+
+/// Which output channels an eye contributes to, matching the `color_mask` of its draw call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelMask {
+    pub red: bool,
+    pub green: bool,
+    pub blue: bool,
+}
+
+impl ChannelMask {
+    const fn new(red: bool, green: bool, blue: bool) -> Self {
+        ChannelMask { red, green, blue }
+    }
+
+    /// The equivalent `glium::DrawParameters::color_mask`, alpha always written.
+    pub fn color_mask(self) -> (bool, bool, bool, bool) {
+        (self.red, self.green, self.blue, true)
+    }
+
+    /// 1 for the channels the eye contributes to and 0 for the others, for shaders combining
+    /// both eyes.
+    pub fn factors(self) -> [f32; 3] {
+        [self.red, self.green, self.blue].map(|on| if on { 1.0 } else { 0.0 })
+    }
+}
+
+/// The filter colors of the glasses, left lens first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorScheme {
+    RedCyan,
+    GreenMagenta,
+    AmberBlue,
+}
+
+impl ColorScheme {
+    /// Channel masks for camera1 (left eye) and camera2 (right eye).
+    pub fn masks(self) -> [ChannelMask; 2] {
+        match self {
+            ColorScheme::RedCyan => [
+                ChannelMask::new(true, false, false),
+                ChannelMask::new(false, true, true),
+            ],
+            ColorScheme::GreenMagenta => [
+                ChannelMask::new(false, true, false),
+                ChannelMask::new(true, false, true),
+            ],
+            ColorScheme::AmberBlue => [
+                ChannelMask::new(true, true, false),
+                ChannelMask::new(false, false, true),
+            ],
+        }
+    }
+
+    /// Dubois' least-squares projections of the left and right eye's RGB into the output for
+    /// these glasses, by columns as GLSL's `mat3` takes them.
+    pub fn dubois(self) -> [[[f32; 3]; 3]; 2] {
+        let rows = match self {
+            ColorScheme::RedCyan => [
+                [
+                    [0.437, 0.449, 0.164],
+                    [-0.062, -0.062, -0.024],
+                    [-0.048, -0.050, -0.017],
+                ],
+                [
+                    [-0.011, -0.032, -0.007],
+                    [0.377, 0.761, 0.009],
+                    [-0.026, -0.093, 1.234],
+                ],
+            ],
+            ColorScheme::GreenMagenta => [
+                [
+                    [-0.062, -0.158, -0.039],
+                    [0.284, 0.668, 0.143],
+                    [-0.015, -0.027, 0.021],
+                ],
+                [
+                    [0.529, 0.705, 0.024],
+                    [-0.016, -0.015, -0.065],
+                    [0.009, 0.075, 0.937],
+                ],
+            ],
+            ColorScheme::AmberBlue => [
+                [
+                    [1.062, -0.205, 0.299],
+                    [-0.026, 0.908, 0.068],
+                    [-0.038, -0.173, 0.022],
+                ],
+                [
+                    [-0.016, -0.123, -0.017],
+                    [0.006, 0.062, -0.017],
+                    [0.094, 0.185, 0.911],
+                ],
+            ],
+        };
+        rows.map(|rows| [0, 1, 2].map(|column| rows.map(|row| row[column])))
+    }
+
+    /// The scheme after this one, for cycling through them at runtime.
+    pub fn next(self) -> Self {
+        match self {
+            ColorScheme::RedCyan => ColorScheme::GreenMagenta,
+            ColorScheme::GreenMagenta => ColorScheme::AmberBlue,
+            ColorScheme::AmberBlue => ColorScheme::RedCyan,
+        }
+    }
+}
+
+impl fmt::Display for ColorScheme {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ColorScheme::RedCyan => "red-cyan",
+            ColorScheme::GreenMagenta => "green-magenta",
+            ColorScheme::AmberBlue => "amber-blue",
+        })
+    }
+}
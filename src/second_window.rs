@@ -0,0 +1,126 @@
+//! `--window2-mode`: a second window showing the cameras another way than the main one, e.g.
+//! side by side for a headset next to an anaglyph monitor.
+//!
+//! glium can't share textures between contexts, so it's drawn offscreen with the main
+//! window's context and presented through `softbuffer` like the software fallback.
+
+use glium::{backend::Facade, Surface};
+use std::{num::NonZeroU32, rc::Rc};
+use winit::{
+    event_loop::EventLoop,
+    window::{Fullscreen, Window, WindowBuilder},
+};
+
+use crate::{camera_uniforms, scheme::ChannelMask, Edge, Eye, Mode, Vertex};
+
+/// One camera as [`SecondWindow::draw`] draws it.
+pub struct View<'a> {
+    pub program: &'a glium::Program,
+    pub texture: &'a glium::texture::Texture2d,
+    pub eye: Eye,
+    pub mask: ChannelMask,
+}
+
+pub struct SecondWindow {
+    pub mode: Mode,
+    window: Rc<Window>,
+    surface: softbuffer::Surface<Rc<Window>, Rc<Window>>,
+}
+
+impl SecondWindow {
+    /// Opens the window fullscreen on the second monitor, if there is one.
+    pub fn open(event_loop: &EventLoop<()>, title: &str, mode: Mode) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            matches!(mode, Mode::Anaglyph | Mode::SideBySide),
+            "--window2-mode can only be anaglyph or side-by-side"
+        );
+        let window = Rc::new(WindowBuilder::new().with_title(title).build(event_loop)?);
+        if let Some(monitor) = event_loop.available_monitors().nth(1) {
+            window.set_fullscreen(Some(Fullscreen::Borderless(Some(monitor))));
+        }
+        // softbuffer's errors aren't `Send`, so they're flattened into strings for anyhow
+        let context = softbuffer::Context::new(window.clone())
+            .map_err(|e| anyhow::anyhow!("Couldn't connect to display: {e}"))?;
+        let surface = softbuffer::Surface::new(&context, window.clone())
+            .map_err(|e| anyhow::anyhow!("Couldn't create software framebuffer: {e}"))?;
+        Ok(SecondWindow {
+            mode,
+            window,
+            surface,
+        })
+    }
+
+    /// The size each eye is drawn at, for [`crate::eyes`].
+    pub fn eye_size(&self) -> (u32, u32) {
+        let size = self.window.inner_size();
+        match self.mode {
+            Mode::SideBySide => (size.width / 2, size.height),
+            _ => (size.width, size.height),
+        }
+    }
+
+    /// Draws both cameras, masked over each other without crosstalk cancellation or comfort
+    /// for the anaglyph, or next to each other with camera1 on the left, and presents them.
+    pub fn draw(
+        &mut self,
+        facade: &impl Facade,
+        (vertex_buffer, index_buffer): (&glium::VertexBuffer<Vertex>, &glium::IndexBuffer<u16>),
+        views: [Option<View>; 2],
+        edge: Edge,
+    ) -> anyhow::Result<()> {
+        let size = self.window.inner_size();
+        let (Some(width), Some(height)) =
+            (NonZeroU32::new(size.width), NonZeroU32::new(size.height))
+        else {
+            return Ok(());
+        };
+        let rendered = glium::texture::Texture2d::empty(facade, width.get(), height.get())?;
+        let mut framebuffer = glium::framebuffer::SimpleFrameBuffer::new(facade, &rendered)?;
+        framebuffer.clear_color(0.0, 0.0, 0.0, 1.0);
+        let (eye_width, _) = self.eye_size();
+        for (index, view) in views.iter().enumerate() {
+            let Some(view) = view else {
+                continue;
+            };
+            let params = match self.mode {
+                Mode::SideBySide => glium::DrawParameters {
+                    viewport: Some(glium::Rect {
+                        left: index as u32 * eye_width,
+                        bottom: 0,
+                        width: eye_width,
+                        height: height.get(),
+                    }),
+                    ..Default::default()
+                },
+                _ => glium::DrawParameters {
+                    color_mask: view.mask.color_mask(),
+                    ..Default::default()
+                },
+            };
+            let uniforms = camera_uniforms(view.texture, view.eye, edge);
+            framebuffer.draw(
+                vertex_buffer,
+                index_buffer,
+                view.program,
+                &uniforms,
+                &params,
+            )?;
+        }
+
+        let rows: Vec<Vec<(u8, u8, u8, u8)>> = rendered.read();
+        self.surface
+            .resize(width, height)
+            .map_err(|e| anyhow::anyhow!("Couldn't resize software framebuffer: {e}"))?;
+        let mut buffer = self
+            .surface
+            .buffer_mut()
+            .map_err(|e| anyhow::anyhow!("Couldn't get software framebuffer: {e}"))?;
+        // textures are bottom up
+        for (out, &(r, g, b, _)) in buffer.iter_mut().zip(rows.iter().rev().flatten()) {
+            *out = (r as u32) << 16 | (g as u32) << 8 | b as u32;
+        }
+        buffer
+            .present()
+            .map_err(|e| anyhow::anyhow!("Couldn't present software framebuffer: {e}"))
+    }
+}
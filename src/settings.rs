@@ -0,0 +1,82 @@
+//! The values that can be tuned while running, starting out as given on the command line.
+
+use crate::{scheme::ColorScheme, Cli};
+
+/// Everything the keyboard shortcuts and the settings panel adjust.
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    /// Shift of camera2 in texels, x to the right and y down.
+    pub camera2_offset: [i32; 2],
+    pub convergence: f32,
+    pub depth_bias: f32,
+    pub crosstalk: f32,
+    pub comfort: f32,
+    pub color_scheme: ColorScheme,
+    pub brightness: f32,
+    pub gamma: f32,
+    pub camera1_gains: [f32; 3],
+    pub camera2_gains: [f32; 3],
+    /// Brightness of camera1 and camera2 relative to each other, for lenses of uneven density.
+    pub eye_gains: [f32; 2],
+}
+
+impl Settings {
+    pub fn new(args: &Cli) -> Self {
+        let gains = |gains: &[f32]| [gains[0], gains[1], gains[2]];
+        Settings {
+            camera2_offset: [args.camera2_offset_x, args.camera2_offset_y],
+            convergence: args.convergence,
+            depth_bias: args.depth_bias,
+            crosstalk: args.crosstalk,
+            comfort: args.comfort,
+            color_scheme: args.color_scheme,
+            brightness: args.brightness,
+            gamma: args.gamma,
+            camera1_gains: gains(&args.camera1_gains),
+            camera2_gains: gains(&args.camera2_gains),
+            eye_gains: [args.eye1_gain, args.eye2_gain],
+        }
+    }
+
+    /// Keeps at least one texel column and row of a camera2 of this size on screen.
+    pub fn clamp_offset(&mut self, width: u32, height: u32) {
+        let (w, h) = (width as i32 - 1, height as i32 - 1);
+        self.camera2_offset[0] = self.camera2_offset[0].clamp(-w, w);
+        self.camera2_offset[1] = self.camera2_offset[1].clamp(-h, h);
+    }
+
+    /// The `--camera1-gains` and `--camera2-gains` flags for the current gains.
+    pub fn gain_flags(&self) -> String {
+        let gains = |[r, g, b]: [f32; 3]| format!("{r:.3},{g:.3},{b:.3}");
+        format!(
+            "--camera1-gains {} --camera2-gains {}",
+            gains(self.camera1_gains),
+            gains(self.camera2_gains)
+        )
+    }
+
+    /// The `--eye1-gain` and `--eye2-gain` flags for the current eye gains.
+    pub fn eye_gain_flags(&self) -> String {
+        let [eye1, eye2] = self.eye_gains;
+        format!("--eye1-gain {eye1:.2} --eye2-gain {eye2:.2}")
+    }
+
+    /// The command line flags that start up with these settings.
+    pub fn flags(&self) -> String {
+        format!(
+            "--convergence {} --depth-bias {} --camera2-offset-x {} --camera2-offset-y {} \
+             --crosstalk {} --comfort {} --color-scheme {} --brightness {} --gamma {} {} {}",
+            self.convergence,
+            self.depth_bias,
+            self.camera2_offset[0],
+            self.camera2_offset[1],
+            self.crosstalk,
+            self.comfort,
+            self.color_scheme,
+            self.brightness,
+            self.gamma,
+            self.gain_flags(),
+            self.eye_gain_flags()
+        )
+    }
+}
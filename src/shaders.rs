@@ -0,0 +1,318 @@
+//! GLSL for drawing a camera image, generated from the options that are fixed at startup.
+
+use glium::{
+    backend::Facade,
+    program::{ProgramCreationError, ProgramCreationInput, ShaderType},
+    Program,
+};
+
+use crate::{camera::Bayer, Deinterlace};
+
+/// Vertex shader of the camera programs, scaling the full-viewport quad by `matrix`.
+pub const CAMERA_VERTEX: &str = "
+    #version 140
+    uniform mat4 matrix;
+    in vec2 position;
+    in vec2 tex_coords;
+    out vec2 v_tex_coords;
+    void main() {
+        gl_Position = matrix * vec4(position, 0.0, 1.0);
+        v_tex_coords = tex_coords;
+    }
+";
+
+/// Vertex shader drawing the full-viewport quad as it is, for passes over whole textures.
+pub const QUAD_VERTEX: &str = "
+    #version 140
+    in vec2 position;
+    in vec2 tex_coords;
+    out vec2 v_tex_coords;
+    void main() {
+        gl_Position = vec4(position, 0.0, 1.0);
+        v_tex_coords = tex_coords;
+    }
+";
+
+/// Mirrors the image horizontally.
+pub const FLIP_X: &str = "new_tex_coords.x = 1.0 - new_tex_coords.x;";
+
+/// Turns the image upside down, i.e. rotates it by 180° for cameras mounted that way.
+pub const FLIP_Y: &str = "new_tex_coords.y = 1.0 - new_tex_coords.y;
+        new_tex_coords.x = 1.0 - new_tex_coords.x;";
+
+/// Converts sRGB-encoded `f_color` to linear light, for drawing into an sRGB framebuffer.
+pub const LINEARIZE: &str = "f_color.rgb = mix(
+                f_color.rgb / 12.92,
+                pow((f_color.rgb + 0.055) / 1.055, vec3(2.4)),
+                step(0.04045, f_color.rgb)
+            );";
+
+/// Premultiplied alpha for transparent windows: the brighter the pixel, the more opaque.
+pub const BRIGHTNESS_ALPHA: &str = "f_color.a = max(f_color.r, max(f_color.g, f_color.b));";
+
+/// Compiles and links a program, or fails with the driver's log next to the numbered source of
+/// the shader it's about.
+pub fn compile(
+    display: &impl Facade,
+    name: &str,
+    vertex: &str,
+    fragment: &str,
+) -> anyhow::Result<Program> {
+    compile_with_output(display, name, vertex, fragment, false)
+}
+
+/// Like [`compile`], but if `linear_output` the program writes linear light, which the
+/// driver sRGB-encodes and blends in linear light when drawing into an sRGB framebuffer.
+pub fn compile_with_output(
+    display: &impl Facade,
+    name: &str,
+    vertex: &str,
+    fragment: &str,
+    linear_output: bool,
+) -> anyhow::Result<Program> {
+    let input = ProgramCreationInput::SourceCode {
+        vertex_shader: vertex,
+        tessellation_control_shader: None,
+        tessellation_evaluation_shader: None,
+        geometry_shader: None,
+        fragment_shader: fragment,
+        transform_feedback_varyings: None,
+        // lets glium enable GL_FRAMEBUFFER_SRGB
+        outputs_srgb: !linear_output,
+        uses_point_size: false,
+    };
+    Program::new(display, input).map_err(|error| {
+        let source = match &error {
+            ProgramCreationError::CompilationError(_, ShaderType::Vertex) => Some(vertex),
+            ProgramCreationError::CompilationError(_, ShaderType::Fragment) => Some(fragment),
+            _ => None,
+        };
+        let log = match &error {
+            ProgramCreationError::CompilationError(log, _)
+            | ProgramCreationError::LinkingError(log) => log.trim().to_owned(),
+            error => error.to_string(),
+        };
+        let mut message = format!("Couldn't build the {name} shader program:\n{log}");
+        if let Some(source) = source {
+            message += "\n\nsource:";
+            for (number, line) in source.lines().enumerate() {
+                message += &format!("\n{:4} {line}", number + 1);
+            }
+        }
+        anyhow::anyhow!(message)
+    })
+}
+
+/// The options a camera's fragment shader is generated for.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ShaderOpts {
+    pub flip_x: bool,
+    /// Insert [`FLIP_Y`].
+    pub flip_y: bool,
+    pub deinterlace: Option<Deinterlace>,
+    /// Demosaic a single-channel texture of raw Bayer data, instead of sampling RGB.
+    pub bayer: Option<Bayer>,
+    /// Set alpha with [`BRIGHTNESS_ALPHA`] instead of leaving it opaque.
+    pub transparent: bool,
+    /// Output linear light with [`LINEARIZE`], for [`compile_with_output`].
+    pub linear: bool,
+    /// Darken toward the edges by the `vignette` uniform's strength and radius, see [`VIGNETTE`].
+    pub vignette: bool,
+}
+
+/// Fragment shader sampling the `crop` rectangle of the `tex` uniform after flipping, shifting
+/// by `offset`, scaling by `magnification` and rotating by `roll`, then converting from YCbCr
+/// if `ycbcr` and applying `gain`, `brightness` and `gamma`, and the `vignette`.
+pub fn fragment_shader(opts: &ShaderOpts) -> String {
+    let snippet = |enabled, snippet| if enabled { snippet } else { "" };
+    let sample = match opts.bayer {
+        Some(bayer) => demosaic(bayer),
+        None => sample(opts.deinterlace).to_owned(),
+    };
+    format!(
+        "
+        #version 140
+        uniform sampler2D tex;
+        // origin and size in texture coordinates
+        uniform vec4 crop;
+        uniform vec2 offset;
+        uniform float roll;
+        uniform float magnification;
+        uniform vec3 gain;
+        uniform bool ycbcr;
+        uniform float brightness;
+        uniform float gamma;
+        // strength and radius
+        uniform vec2 vignette;
+        in vec2 v_tex_coords;
+        out vec4 f_color;
+        {functions}
+        void main() {{
+            vec2 new_tex_coords = v_tex_coords;
+            {flip_y}
+            {flip_x}
+            new_tex_coords -= offset;
+            // rotate in texel space so non-square images aren't sheared
+            vec2 size = vec2(textureSize(tex, 0)) * crop.zw;
+            vec2 centered = (new_tex_coords - 0.5) * size / magnification;
+            centered = mat2(cos(roll), sin(roll), -sin(roll), cos(roll)) * centered;
+            new_tex_coords = crop.xy + (centered / size + 0.5) * crop.zw;
+            size = vec2(textureSize(tex, 0));
+            {sample}
+            if (ycbcr) {{
+                // full-range JFIF YCbCr, matrix columns are Y, Cb and Cr
+                f_color.rgb = mat3(
+                    1.0, 1.0, 1.0,
+                    0.0, -0.344136, 1.772,
+                    1.402, -0.714136, 0.0
+                ) * (f_color.rgb - vec3(0.0, 0.5, 0.5));
+            }}
+            f_color.rgb *= gain;
+            f_color.rgb = pow(max(f_color.rgb * brightness, 0.0), vec3(1.0 / gamma));
+            {vignette}
+            {alpha}
+            {linearize}
+        }}
+        ",
+        flip_y = snippet(opts.flip_y, FLIP_Y),
+        flip_x = snippet(opts.flip_x, FLIP_X),
+        functions = snippet(opts.bayer.is_some(), BAYER_AT),
+        vignette = snippet(opts.vignette, VIGNETTE),
+        alpha = snippet(opts.transparent, BRIGHTNESS_ALPHA),
+        linearize = snippet(opts.linear, LINEARIZE),
+    )
+}
+
+/// GLSL that samples `tex` at `new_tex_coords` into `f_color`, given its `size` in texels.
+fn sample(deinterlace: Option<Deinterlace>) -> &'static str {
+    match deinterlace {
+        None => "f_color = texture(tex, new_tex_coords);",
+        Some(Deinterlace::Bob) => {
+            "new_tex_coords.y = (floor(new_tex_coords.y * size.y / 2.0) * 2.0 + 0.5) / size.y;
+            f_color = texture(tex, new_tex_coords);"
+        }
+        Some(Deinterlace::Blend) => {
+            "vec2 next_line = new_tex_coords - vec2(0.0, 1.0 / size.y);
+            f_color = mix(texture(tex, new_tex_coords), texture(tex, next_line), 0.5);"
+        }
+    }
+}
+
+/// GLSL that darkens `f_color` by up to the `vignette` strength from its radius out to the
+/// corners.
+///
+/// The distance is taken in the drawn quad's coordinates, before any flip, shift or rotation,
+/// so both eyes are darkened in the same place and the vignette adds no disparity.
+const VIGNETTE: &str = "float from_center = length(v_tex_coords * 2.0 - 1.0);
+            f_color.rgb *= 1.0 - vignette.x * smoothstep(vignette.y, sqrt(2.0), from_center);";
+
+/// Reads a raw sample, mirroring at the edges so the pattern's phase is kept.
+const BAYER_AT: &str = "
+        float bayer_at(ivec2 texel, ivec2 size) {
+            texel = abs(texel);
+            texel = min(texel, 2 * (size - 1) - texel);
+            return texelFetch(tex, texel, 0).r;
+        }
+";
+
+/// GLSL that bilinearly demosaics the raw sample nearest to `new_tex_coords` into `f_color`.
+fn demosaic(bayer: Bayer) -> String {
+    let (red_x, red_y) = bayer.red();
+    format!(
+        "ivec2 texels = ivec2(size);
+            ivec2 texel = clamp(ivec2(floor(new_tex_coords * size)), ivec2(0), texels - 1);
+            // texture rows are bottom up, the pattern is given from the top left
+            ivec2 site = (ivec2(texel.x, texels.y - 1 - texel.y) + ivec2({red_x}, {red_y})) % 2;
+            float center = bayer_at(texel, texels);
+            float horizontal = (bayer_at(texel + ivec2(-1, 0), texels)
+                + bayer_at(texel + ivec2(1, 0), texels)) / 2.0;
+            float vertical = (bayer_at(texel + ivec2(0, -1), texels)
+                + bayer_at(texel + ivec2(0, 1), texels)) / 2.0;
+            float diagonal = (bayer_at(texel + ivec2(-1, -1), texels)
+                + bayer_at(texel + ivec2(1, -1), texels)
+                + bayer_at(texel + ivec2(-1, 1), texels)
+                + bayer_at(texel + ivec2(1, 1), texels)) / 4.0;
+            float around = (horizontal + vertical) / 2.0;
+            if (site == ivec2(0, 0)) {{
+                f_color = vec4(center, around, diagonal, 1.0);
+            }} else if (site == ivec2(1, 1)) {{
+                f_color = vec4(diagonal, around, center, 1.0);
+            }} else if (site == ivec2(1, 0)) {{
+                // green in a red row
+                f_color = vec4(horizontal, center, vertical, 1.0);
+            }} else {{
+                f_color = vec4(vertical, center, horizontal, 1.0);
+            }}"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_has_no_optional_snippets() {
+        let shader = fragment_shader(&ShaderOpts::default());
+        assert!(shader.contains("#version 140"));
+        assert!(!shader.contains(FLIP_X));
+        assert!(!shader.contains(FLIP_Y));
+        assert!(!shader.contains(BRIGHTNESS_ALPHA));
+        assert!(!shader.contains(LINEARIZE));
+        assert!(!shader.contains(VIGNETTE));
+        assert!(shader.contains(sample(None)));
+    }
+
+    #[test]
+    fn options_insert_their_snippets() {
+        let shader = fragment_shader(&ShaderOpts {
+            flip_x: true,
+            flip_y: true,
+            deinterlace: Some(Deinterlace::Blend),
+            bayer: None,
+            transparent: true,
+            linear: true,
+            vignette: true,
+        });
+        assert!(shader.contains(FLIP_X));
+        assert!(shader.contains(FLIP_Y));
+        assert!(shader.contains(BRIGHTNESS_ALPHA));
+        assert!(shader.contains(LINEARIZE));
+        assert!(shader.contains("next_line"));
+        assert!(shader.contains(VIGNETTE));
+    }
+
+    #[test]
+    fn flips_happen_before_the_offset() {
+        let shader = fragment_shader(&ShaderOpts {
+            flip_x: true,
+            ..Default::default()
+        });
+        let flip = shader.find(FLIP_X).unwrap();
+        assert!(flip < shader.find("new_tex_coords -= offset").unwrap());
+    }
+
+    #[test]
+    fn braces_are_balanced() {
+        for deinterlace in [None, Some(Deinterlace::Bob), Some(Deinterlace::Blend)] {
+            let shader = fragment_shader(&ShaderOpts {
+                deinterlace,
+                ..Default::default()
+            });
+            assert_eq!(shader.matches('{').count(), shader.matches('}').count());
+        }
+    }
+
+    #[test]
+    fn bayer_patterns_have_their_red_site() {
+        for (bayer, red) in [(Bayer::Rggb, "ivec2(0, 0)"), (Bayer::Bggr, "ivec2(1, 1)")] {
+            let shader = fragment_shader(&ShaderOpts {
+                bayer: Some(bayer),
+                ..Default::default()
+            });
+            assert!(shader.contains(BAYER_AT));
+            assert!(shader.contains(&format!("+ {red}) % 2")));
+            assert!(!shader.contains(sample(None)));
+            assert_eq!(shader.matches('{').count(), shader.matches('}').count());
+        }
+    }
+}
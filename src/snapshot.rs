@@ -0,0 +1,177 @@
+//! `--once`: composites a single anaglyph from both cameras offscreen and saves it, for
+//! scripts taking stereo snapshots, and `--headless`, which keeps compositing them into the
+//! output sinks without a window.
+
+use anyhow::Context;
+use std::{
+    fs,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    camera::{self, Trigger},
+    metrics,
+    output::{self, OutputSink},
+    renderer::{AnaglyphRenderer, FrameMetadata},
+    Camera, Cli, Frame,
+};
+
+/// How long to keep looking for a better paired set once both cameras have a frame.
+const PAIRING: Duration = Duration::from_millis(500);
+/// Capture times this close count as the same instant, ending the search early.
+const SIMULTANEOUS: Duration = Duration::from_millis(5);
+/// How long `--headless` waits for a triggered pair before firing the triggers again.
+const RETRIGGER: Duration = Duration::from_secs(1);
+
+/// Waits for both cameras unless they're `stills`, firing `triggers` first, renders the
+/// anaglyph at camera1's resolution or --output-width and --output-height and writes it to
+/// `path` as a binary PPM.
+///
+/// Crosstalk cancellation and the comfort filter aren't applied.
+pub fn run(
+    args: &Cli,
+    mut cameras: [&mut Camera; 2],
+    stills: bool,
+    triggers: &[Trigger],
+    path: &Path,
+    render_ms: &mut metrics::Reservoir,
+) -> anyhow::Result<()> {
+    let frames = if stills {
+        [0, 1].map(|index| frame(cameras[index]))
+    } else {
+        let timeout = Duration::from_secs_f32(args.once_timeout);
+        camera::fire(triggers);
+        for (index, camera) in cameras.iter().enumerate() {
+            anyhow::ensure!(
+                camera.wait_for_frame(timeout),
+                "camera{} hasn't delivered a frame within {timeout:?}",
+                index + 1
+            );
+        }
+        pair(&mut cameras)
+    };
+
+    let cameras = [&*cameras[0], &*cameras[1]];
+    let mut renderer = AnaglyphRenderer::new(args)?;
+    renderer.on_frame(|frame| record_render_time(render_ms, frame));
+    let (rgba, (width, height)) = render(&mut renderer, cameras, &frames, stills)?;
+    let mut ppm = format!("P6\n{width} {height}\n255\n").into_bytes();
+    ppm.extend(output::top_down_rgb(&rgba, width));
+    fs::write(path, ppm).with_context(|| format!("Couldn't write {}", path.display()))?;
+    println!("Wrote {width}x{height} anaglyph to {}", path.display());
+    Ok(())
+}
+
+/// `--headless`: renders the anaglyph like [`run`] whenever a camera has a new frame and
+/// writes it to `sinks`, until none of them takes frames anymore. `stills` are written once.
+///
+/// With `triggers`, they're fired for every pair, which is rendered once both frames are in.
+/// --print-latency counts until a frame's first anaglyph has been read back for the sinks.
+pub fn stream(
+    args: &Cli,
+    cameras: [&mut Camera; 2],
+    stills: bool,
+    triggers: &[Trigger],
+    mut sinks: Vec<Box<dyn OutputSink>>,
+    render_ms: &mut metrics::Reservoir,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !sinks.is_empty(),
+        "--headless needs --output-pipe or --output-v4l2 to write to"
+    );
+    let mut renderer = AnaglyphRenderer::new(args)?;
+    let mut latency = args.print_latency.then(metrics::Latency::new);
+    renderer.on_frame(move |frame| {
+        record_render_time(render_ms, frame);
+        let Some(latency) = &mut latency else {
+            return;
+        };
+        for camera in 0..2 {
+            // only the first anaglyph with each frame counts
+            if !frame.repeated[camera] {
+                latency.record(camera, frame.staleness[camera]);
+            }
+        }
+        if let Some(summary) = latency.summary() {
+            println!("{summary}");
+        }
+    });
+    let mut rendered = [None; 2];
+    camera::fire(triggers);
+    let mut fired = Instant::now();
+    while !sinks.is_empty() {
+        let captured = [0, 1].map(|index| *cameras[index].captured.lock().unwrap());
+        let waiting = match triggers {
+            [] => captured == rendered,
+            _ => captured[0] == rendered[0] || captured[1] == rendered[1],
+        };
+        if !stills && (captured.contains(&None) || waiting) {
+            // in case a trigger got lost
+            if !triggers.is_empty() && fired.elapsed() > RETRIGGER {
+                camera::fire(triggers);
+                fired = Instant::now();
+            }
+            thread::sleep(Duration::from_millis(2));
+            continue;
+        }
+        rendered = captured;
+        camera::fire(triggers);
+        fired = Instant::now();
+        let frames = [0, 1].map(|index| frame(cameras[index]));
+        let (rgba, (width, height)) =
+            render(&mut renderer, [&*cameras[0], &*cameras[1]], &frames, stills)?;
+        output::write_all(&mut sinks, &rgba, width, height);
+        if stills {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Composites `frames`, injecting them like frames from an embedder if they're `stills`,
+/// which are plain RGB.
+fn render(
+    renderer: &mut AnaglyphRenderer,
+    cameras: [&Camera; 2],
+    frames: &[Frame; 2],
+    stills: bool,
+) -> anyhow::Result<(Vec<u8>, (u32, u32))> {
+    if !stills {
+        return renderer.composite(cameras, frames);
+    }
+    let [left, right] = frames;
+    renderer.set_left_frame(&left.data, left.width, left.height)?;
+    renderer.set_right_frame(&right.data, right.width, right.height)?;
+    renderer.render()
+}
+
+fn record_render_time(render_ms: &mut metrics::Reservoir, frame: &FrameMetadata) {
+    render_ms.record(frame.render_time.as_secs_f32() * 1000.0);
+}
+
+/// Takes the pair of frames captured closest together within [`PAIRING`].
+fn pair(cameras: &mut [&mut Camera; 2]) -> [Frame; 2] {
+    let deadline = Instant::now() + PAIRING;
+    let mut best: Option<(Duration, [Frame; 2])> = None;
+    loop {
+        let [camera1, camera2] = &mut *cameras;
+        let (frame1, frame2) = (camera1.frame(), camera2.frame());
+        let (captured1, captured2) = (frame1.timestamp, frame2.timestamp);
+        let skew = captured1.max(captured2) - captured1.min(captured2);
+        if best.as_ref().map_or(true, |(best, _)| skew < *best) {
+            best = Some((skew, [frame1.clone(), frame2.clone()]));
+        }
+        if skew <= SIMULTANEOUS || Instant::now() > deadline {
+            let (skew, frames) = best.unwrap();
+            println!("Frames captured {skew:?} apart");
+            return frames;
+        }
+        thread::sleep(Duration::from_millis(2));
+    }
+}
+
+fn frame(camera: &mut Camera) -> Frame {
+    camera.frame().clone()
+}
@@ -0,0 +1,191 @@
+//! Frame sources other than V4L devices.
+
+use anyhow::Context;
+use std::{
+    io::{self, Read},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    process::{Child, Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+/// Something that produces interleaved RGB frames of a fixed size for one eye.
+pub trait FrameSource: Send + 'static {
+    /// Blocks until the next frame is available.
+    fn next_frame(&mut self) -> anyhow::Result<Vec<u8>>;
+
+    /// Produces frames of another size from the next one on, if the source can.
+    fn set_resolution(&mut self, _width: u32, _height: u32) -> anyhow::Result<()> {
+        anyhow::bail!("the resolution of this source is fixed")
+    }
+}
+
+/// Receives frames pushed by another process over a Unix domain socket.
+///
+/// The renderer listens on the socket and accepts one producer at a time; when it disconnects,
+/// the next one is accepted. Each frame is sent as
+///
+/// ```text
+/// u32 little-endian   length of the frame data in bytes, always width * height * 3
+/// [u8; length]        interleaved RGB, 8 bits per channel, rows top to bottom
+/// ```
+pub struct SocketSource {
+    path: PathBuf,
+    listener: UnixListener,
+    stream: Option<UnixStream>,
+    frame_len: usize,
+}
+
+impl SocketSource {
+    pub fn bind(path: &Path, width: u32, height: u32) -> anyhow::Result<Self> {
+        // a socket left behind by a previous run would make bind fail, but anything else there
+        // isn't ours to delete
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+            Ok(_) => anyhow::bail!("{} exists and isn't a socket", path.display()),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+        let listener = UnixListener::bind(path)?;
+        println!("Waiting for frames on {}", path.display());
+        Ok(SocketSource {
+            path: path.to_owned(),
+            listener,
+            stream: None,
+            frame_len: width as usize * height as usize * 3,
+        })
+    }
+
+    /// Reads one frame, or skips over it and returns its length if that's not `expected_len`.
+    fn read_frame(
+        stream: &mut UnixStream,
+        expected_len: usize,
+    ) -> io::Result<Result<Vec<u8>, u32>> {
+        let mut len = [0; 4];
+        stream.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len);
+        if len as usize != expected_len {
+            io::copy(&mut stream.by_ref().take(len.into()), &mut io::sink())?;
+            return Ok(Err(len));
+        }
+        let mut data = vec![0; expected_len];
+        stream.read_exact(&mut data)?;
+        Ok(Ok(data))
+    }
+}
+
+impl FrameSource for SocketSource {
+    fn next_frame(&mut self) -> anyhow::Result<Vec<u8>> {
+        loop {
+            let stream = match &mut self.stream {
+                Some(stream) => stream,
+                None => {
+                    let (stream, _) = self.listener.accept()?;
+                    println!("\n{}: producer connected", self.path.display());
+                    self.stream.insert(stream)
+                }
+            };
+            match Self::read_frame(stream, self.frame_len) {
+                Ok(Ok(data)) => return Ok(data),
+                Ok(Err(len)) => {
+                    anyhow::bail!("skipped frame of {len} bytes, expected {}", self.frame_len)
+                }
+                Err(error) => {
+                    if error.kind() != io::ErrorKind::UnexpectedEof {
+                        eprintln!("\n{}: {error}", self.path.display());
+                    }
+                    println!("\n{}: producer disconnected", self.path.display());
+                    self.stream = None;
+                }
+            }
+        }
+    }
+}
+
+/// Plays a video file by piping it through `ffmpeg`, at the file's own frame rate and starting
+/// over at the end.
+pub struct FileSource {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    ffmpeg: Child,
+}
+
+impl FileSource {
+    pub fn open(path: &Path, width: u32, height: u32) -> anyhow::Result<Self> {
+        Ok(FileSource {
+            path: path.to_owned(),
+            width,
+            height,
+            ffmpeg: Self::spawn(path, width, height)?,
+        })
+    }
+
+    fn spawn(path: &Path, width: u32, height: u32) -> anyhow::Result<Child> {
+        Command::new("ffmpeg")
+            // -re reads the input in real time instead of as fast as possible
+            .args(["-loglevel", "error", "-re", "-stream_loop", "-1", "-i"])
+            .arg(path)
+            .arg("-vf")
+            .arg(format!("scale={width}:{height}"))
+            .args(["-f", "rawvideo", "-pix_fmt", "rgb24", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("Couldn't start ffmpeg")
+    }
+}
+
+impl FrameSource for FileSource {
+    fn next_frame(&mut self) -> anyhow::Result<Vec<u8>> {
+        let mut data = vec![0; self.width as usize * self.height as usize * 3];
+        let stdout = self
+            .ffmpeg
+            .stdout
+            .as_mut()
+            .expect("ffmpeg is spawned with piped stdout");
+        if stdout.read_exact(&mut data).is_ok() {
+            return Ok(data);
+        }
+        let status = self.ffmpeg.wait()?;
+        // don't spin on a file ffmpeg can't play
+        thread::sleep(Duration::from_secs(1));
+        self.ffmpeg = Self::spawn(&self.path, self.width, self.height)?;
+        anyhow::bail!("ffmpeg exited with {status}, restarted it")
+    }
+
+    /// Restarts ffmpeg scaling to the new size, from the start of the file.
+    fn set_resolution(&mut self, width: u32, height: u32) -> anyhow::Result<()> {
+        let ffmpeg = Self::spawn(&self.path, width, height)?;
+        let mut previous = std::mem::replace(&mut self.ffmpeg, ffmpeg);
+        (self.width, self.height) = (width, height);
+        // it may already have exited
+        let _ = previous.kill();
+        previous.wait()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binding_replaces_a_stale_socket_but_not_a_file() {
+        let dir = std::env::temp_dir().join(format!("anaglyph-socket-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("eye.sock");
+        drop(UnixListener::bind(&socket).unwrap());
+        assert!(SocketSource::bind(&socket, 2, 2).is_ok());
+
+        let file = dir.join("eye.txt");
+        std::fs::write(&file, "keep").unwrap();
+        assert!(SocketSource::bind(&file, 2, 2).is_err());
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "keep");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,214 @@
+//! Loading static stereo pairs from disk, for viewing existing stereo photos.
+
+use anyhow::Context;
+use jpeg_decoder as jpeg;
+use std::path::Path;
+
+use crate::camera;
+
+/// A decoded, interleaved RGB image.
+pub struct Image {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+}
+
+fn decode_jpeg(bytes: &[u8]) -> anyhow::Result<Image> {
+    let mut decoder = jpeg::Decoder::new(bytes);
+    let data = decoder.decode().context("Couldn't decode JPEG")?;
+    let info = decoder.info().context("JPEG has no frame header")?;
+    let data = camera::to_rgb(data, info.pixel_format, false)?;
+    Ok(Image {
+        data,
+        width: info.width.into(),
+        height: info.height.into(),
+    })
+}
+
+/// Loads the first two images of a Multi Picture Object file (as written by most stereo
+/// cameras), in left, right order.
+pub fn load_mpo(path: &Path) -> anyhow::Result<[Image; 2]> {
+    let bytes = std::fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    let offsets = mpo_image_offsets(&bytes).context("Couldn't parse MPO index")?;
+    let [left, right, ..] = offsets[..] else {
+        anyhow::bail!("{} contains fewer than two images", path.display());
+    };
+    let image = |(offset, size): (usize, usize)| {
+        bytes
+            .get(offset..offset + size)
+            .context("MPO image lies outside the file")
+            .and_then(decode_jpeg)
+    };
+    Ok([image(left)?, image(right)?])
+}
+
+/// Loads a side-by-side JPEG, left half for the left eye and right half for the right eye.
+pub fn load_sbs(path: &Path) -> anyhow::Result<[Image; 2]> {
+    let bytes = std::fs::read(path).with_context(|| format!("Couldn't read {}", path.display()))?;
+    Ok(split(decode_jpeg(&bytes)?))
+}
+
+/// Cuts an image into its left and right halves, dropping the last column of odd widths.
+fn split(image: Image) -> [Image; 2] {
+    let half = image.width / 2;
+    let row = image.width as usize * 3;
+    let split = |start: usize| Image {
+        data: image
+            .data
+            .chunks_exact(row)
+            .flat_map(|line| &line[start..start + half as usize * 3])
+            .copied()
+            .collect(),
+        width: half,
+        height: image.height,
+    };
+    [split(0), split(half as usize * 3)]
+}
+
+/// Returns `(offset, size)` of each image listed in the MP Index IFD of the first image's APP2
+/// segment (CIPA DC-007).
+fn mpo_image_offsets(bytes: &[u8]) -> anyhow::Result<Vec<(usize, usize)>> {
+    const MP_ENTRY: u16 = 0xB002;
+
+    anyhow::ensure!(bytes.starts_with(&[0xFF, 0xD8]), "not a JPEG file");
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() && bytes[pos] == 0xFF {
+        let marker = bytes[pos + 1];
+        let length = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let segment = bytes
+            .get(pos + 4..pos + 2 + length)
+            .context("truncated JPEG segment")?;
+        if marker == 0xE2 && segment.starts_with(b"MPF\0") {
+            // offsets are relative to the start of this TIFF header
+            let tiff_start = pos + 8;
+            let tiff = &segment[4..];
+            let big_endian = match tiff.get(..2) {
+                Some(b"MM") => true,
+                Some(b"II") => false,
+                _ => anyhow::bail!("invalid MPF byte order"),
+            };
+            let u16_at = |i: usize| -> anyhow::Result<u16> {
+                let b: [u8; 2] = tiff.get(i..i + 2).context("truncated MPF")?.try_into()?;
+                Ok(if big_endian {
+                    u16::from_be_bytes(b)
+                } else {
+                    u16::from_le_bytes(b)
+                })
+            };
+            let u32_at = |i: usize| -> anyhow::Result<u32> {
+                let b: [u8; 4] = tiff.get(i..i + 4).context("truncated MPF")?.try_into()?;
+                Ok(if big_endian {
+                    u32::from_be_bytes(b)
+                } else {
+                    u32::from_le_bytes(b)
+                })
+            };
+
+            let ifd = u32_at(4)? as usize;
+            for tag in 0..u16_at(ifd)? as usize {
+                let entry = ifd + 2 + tag * 12;
+                if u16_at(entry)? != MP_ENTRY {
+                    continue;
+                }
+                let count = u32_at(entry + 4)? as usize / 16;
+                let entries = u32_at(entry + 8)? as usize;
+                return (0..count)
+                    .map(|i| {
+                        let size = u32_at(entries + i * 16 + 4)? as usize;
+                        let offset = u32_at(entries + i * 16 + 8)? as usize;
+                        // the first image's offset is always 0, meaning the start of the file
+                        let offset = if offset == 0 { 0 } else { tiff_start + offset };
+                        Ok((offset, size))
+                    })
+                    .collect();
+            }
+            anyhow::bail!("MPF segment has no MP Entry tag");
+        }
+        // image data starts at SOS, no more metadata after this
+        if marker == 0xDA {
+            break;
+        }
+        pos += 2 + length;
+    }
+    anyhow::bail!("no MPF segment found")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The start of an MPO whose MP Index IFD lists a 1000 byte image at the start of the file
+    /// and a 500 byte one 1000 bytes after the TIFF header, which is at byte 10.
+    fn mpo(big_endian: bool) -> Vec<u8> {
+        let u16_bytes = |value: u16| match big_endian {
+            true => value.to_be_bytes(),
+            false => value.to_le_bytes(),
+        };
+        let u32_bytes = |value: u32| match big_endian {
+            true => value.to_be_bytes(),
+            false => value.to_le_bytes(),
+        };
+        let mut tiff = Vec::new();
+        tiff.extend_from_slice(if big_endian { b"MM" } else { b"II" });
+        tiff.extend_from_slice(&u16_bytes(42));
+        // the IFD right after the header, with one entry and no next IFD
+        tiff.extend_from_slice(&u32_bytes(8));
+        tiff.extend_from_slice(&u16_bytes(1));
+        tiff.extend_from_slice(&u16_bytes(0xB002));
+        tiff.extend_from_slice(&u16_bytes(7));
+        tiff.extend_from_slice(&u32_bytes(32));
+        tiff.extend_from_slice(&u32_bytes(26));
+        tiff.extend_from_slice(&u32_bytes(0));
+        for (size, offset) in [(1000, 0), (500, 1000)] {
+            tiff.extend_from_slice(&u32_bytes(0));
+            tiff.extend_from_slice(&u32_bytes(size));
+            tiff.extend_from_slice(&u32_bytes(offset));
+            tiff.extend_from_slice(&[0; 4]);
+        }
+
+        let mut bytes = vec![0xFF, 0xD8, 0xFF, 0xE2];
+        bytes.extend_from_slice(&(2 + 4 + tiff.len() as u16).to_be_bytes());
+        bytes.extend_from_slice(b"MPF\0");
+        bytes.extend_from_slice(&tiff);
+        // start of scan
+        bytes.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02]);
+        bytes
+    }
+
+    #[test]
+    fn finds_the_images_in_either_byte_order() {
+        for big_endian in [true, false] {
+            assert_eq!(
+                mpo_image_offsets(&mpo(big_endian)).unwrap(),
+                [(0, 1000), (1010, 500)],
+                "big endian: {big_endian}"
+            );
+        }
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let bytes = mpo(true);
+        // everything but the start of scan is needed
+        for length in 0..bytes.len() - 4 {
+            assert!(
+                mpo_image_offsets(&bytes[..length]).is_err(),
+                "{length} bytes"
+            );
+        }
+    }
+
+    #[test]
+    fn side_by_side_images_split_down_the_middle() {
+        // 5x1, the last pixel dropped
+        let image = Image {
+            data: (0..15).collect(),
+            width: 5,
+            height: 1,
+        };
+        let [left, right] = split(image);
+        assert_eq!((left.width, left.height), (2, 1));
+        assert_eq!(left.data, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(right.data, [6, 7, 8, 9, 10, 11]);
+    }
+}
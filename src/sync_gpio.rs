@@ -0,0 +1,122 @@
+//! `--sync-gpio`: flips a GPIO pin whenever a new stereo pair is presented, for external
+//! hardware like IR illuminators or loggers to sync to, e.g. on a Raspberry Pi.
+//!
+//! The pin is driven through the kernel's GPIO character device, so it works on any Linux
+//! board that has one; it needs the `gpio` feature, and without either it does nothing.
+
+use std::{fs::File, os::fd::AsRawFd, path::Path};
+
+/// `GPIOHANDLES_MAX` of the v1 GPIO character device ABI.
+const MAX_LINES: usize = 64;
+#[cfg(feature = "gpio")]
+const GPIOHANDLE_REQUEST_OUTPUT: u32 = 1 << 1;
+/// `_IOWR(0xB4, 0x03, struct gpiohandle_request)`.
+#[cfg(feature = "gpio")]
+const GPIO_GET_LINEHANDLE_IOCTL: libc::c_ulong = 0xC16C_B403;
+/// `_IOWR(0xB4, 0x09, struct gpiohandle_data)`.
+const GPIOHANDLE_SET_LINE_VALUES_IOCTL: libc::c_ulong = 0xC040_B409;
+
+/// `struct gpiohandle_request`.
+#[cfg(feature = "gpio")]
+#[repr(C)]
+struct HandleRequest {
+    line_offsets: [u32; MAX_LINES],
+    flags: u32,
+    default_values: [u8; MAX_LINES],
+    consumer_label: [u8; 32],
+    lines: u32,
+    fd: libc::c_int,
+}
+
+// the sizes are part of the ioctl numbers
+#[cfg(feature = "gpio")]
+const _: () = assert!(std::mem::size_of::<HandleRequest>() == 364);
+const _: () = assert!(std::mem::size_of::<HandleData>() == 64);
+
+/// `struct gpiohandle_data`.
+#[repr(C)]
+struct HandleData {
+    values: [u8; MAX_LINES],
+}
+
+pub struct SyncGpio {
+    line: File,
+    high: bool,
+}
+
+impl SyncGpio {
+    /// Claims `pin` of `chip` as an output starting low, or warns and returns `None` if it
+    /// can't, e.g. because there's no such chip on this machine.
+    pub fn open(chip: &Path, pin: u32) -> Option<Self> {
+        match request_output(chip, pin) {
+            Ok(line) => {
+                println!("Flipping GPIO {pin} of {} with every pair", chip.display());
+                Some(SyncGpio { line, high: false })
+            }
+            Err(error) => {
+                eprintln!(
+                    "WARNING: couldn't claim GPIO {pin} of {}, --sync-gpio does nothing: {error}",
+                    chip.display()
+                );
+                None
+            }
+        }
+    }
+
+    /// Flips the pin, so every edge marks a pair.
+    pub fn toggle(&mut self) {
+        self.high = !self.high;
+        let mut data = HandleData {
+            values: [0; MAX_LINES],
+        };
+        data.values[0] = self.high.into();
+        // SAFETY: the line handle takes a `gpiohandle_data`, which `HandleData` mirrors
+        let result = unsafe {
+            libc::ioctl(
+                self.line.as_raw_fd(),
+                GPIOHANDLE_SET_LINE_VALUES_IOCTL,
+                &mut data,
+            )
+        };
+        if result < 0 {
+            eprintln!(
+                "\ncouldn't set the sync GPIO: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
+#[cfg(feature = "gpio")]
+fn request_output(chip: &Path, pin: u32) -> std::io::Result<File> {
+    use std::os::fd::FromRawFd;
+
+    let chip = File::open(chip)?;
+    let mut request = HandleRequest {
+        line_offsets: [0; MAX_LINES],
+        flags: GPIOHANDLE_REQUEST_OUTPUT,
+        default_values: [0; MAX_LINES],
+        consumer_label: [0; 32],
+        lines: 1,
+        fd: -1,
+    };
+    request.line_offsets[0] = pin;
+    let label = b"anaglyph_robot";
+    request.consumer_label[..label.len()].copy_from_slice(label);
+    // SAFETY: the chip takes a `gpiohandle_request`, which `HandleRequest` mirrors, and fills
+    // in the line handle's descriptor, which is then owned by the returned file
+    unsafe {
+        if libc::ioctl(chip.as_raw_fd(), GPIO_GET_LINEHANDLE_IOCTL, &mut request) < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(File::from_raw_fd(request.fd))
+    }
+}
+
+#[cfg(not(feature = "gpio"))]
+fn request_output(_chip: &Path, _pin: u32) -> std::io::Result<File> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "built without the gpio feature",
+    ))
+}
@@ -0,0 +1,129 @@
+//! Hands frames from a capture thread to the renderer without either waiting for the other.
+//!
+//! There are three slots: the writer fills its back slot and swaps it with the middle one, the
+//! reader swaps its front slot with the middle one whenever that holds a newer frame. The
+//! middle slot's index is the only state shared between them, so the newest published frame
+//! always wins, frames nobody got to are simply overwritten, and the slot being read is never
+//! written to.
+
+use std::{
+    cell::UnsafeCell,
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+};
+
+/// Set in [`Shared::middle`] when the middle slot holds a frame the reader hasn't taken yet.
+const FRESH: u8 = 0b100;
+const INDEX: u8 = 0b011;
+
+struct Shared<T> {
+    slots: [UnsafeCell<T>; 3],
+    /// Index of the middle slot, or'ed with [`FRESH`].
+    middle: AtomicU8,
+}
+
+// Each slot is only ever accessed by whoever owns its index, and indices only change hands
+// through `middle`, whose acquire/release swaps order the accesses on either side.
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+/// The capture thread's end, publishing frames.
+pub struct Writer<T> {
+    shared: Arc<Shared<T>>,
+    back: u8,
+}
+
+/// The renderer's end, reading the newest published frame.
+pub struct Reader<T> {
+    shared: Arc<Shared<T>>,
+    front: u8,
+}
+
+/// A triple buffer whose reader sees `initial` until the first frame is published.
+pub fn new<T: Clone>(initial: T) -> (Writer<T>, Reader<T>) {
+    let shared = Arc::new(Shared {
+        slots: [0; 3].map(|_| UnsafeCell::new(initial.clone())),
+        middle: AtomicU8::new(1),
+    });
+    (
+        Writer {
+            shared: Arc::clone(&shared),
+            back: 0,
+        },
+        Reader { shared, front: 2 },
+    )
+}
+
+impl<T> Writer<T> {
+    /// Makes `value` the newest frame, replacing any published one the reader hasn't taken.
+    pub fn publish(&mut self, value: T) {
+        // SAFETY: the back slot belongs to the writer until it's swapped into the middle
+        unsafe { *self.shared.slots[self.back as usize].get() = value };
+        let previous = self.shared.middle.swap(self.back | FRESH, Ordering::AcqRel);
+        self.back = previous & INDEX;
+    }
+}
+
+impl<T> Reader<T> {
+    /// The newest published frame.
+    pub fn read(&mut self) -> &T {
+        if self.shared.middle.load(Ordering::Relaxed) & FRESH != 0 {
+            let middle = self.shared.middle.swap(self.front, Ordering::AcqRel);
+            self.front = middle & INDEX;
+        }
+        // SAFETY: the front slot belongs to the reader until it's swapped into the middle,
+        // which needs `&mut self` and so can't happen while the returned borrow lives
+        unsafe { &*self.shared.slots[self.front as usize].get() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn reads_initial_value_until_published() {
+        let (mut writer, mut reader) = new(0);
+        assert_eq!(*reader.read(), 0);
+        writer.publish(1);
+        assert_eq!(*reader.read(), 1);
+        assert_eq!(*reader.read(), 1);
+    }
+
+    #[test]
+    fn newest_wins() {
+        let (mut writer, mut reader) = new(0);
+        for value in 1..=5 {
+            writer.publish(value);
+        }
+        assert_eq!(*reader.read(), 5);
+        writer.publish(6);
+        writer.publish(7);
+        assert_eq!(*reader.read(), 7);
+    }
+
+    #[test]
+    fn no_torn_frames_under_contention() {
+        const FRAMES: u32 = 20_000;
+        const FRAME_LEN: usize = 4096;
+        let (mut writer, mut reader) = new(vec![0; FRAME_LEN]);
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                for frame in 1..=FRAMES {
+                    writer.publish(vec![frame; FRAME_LEN]);
+                }
+            });
+            let mut last = 0;
+            while last < FRAMES {
+                let frame = reader.read();
+                assert_eq!(frame.len(), FRAME_LEN);
+                let first = frame[0];
+                assert!(frame.iter().all(|&value| value == first), "torn frame");
+                assert!(first >= last, "frame {first} read after {last}");
+                last = first;
+            }
+        });
+    }
+}